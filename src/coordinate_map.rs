@@ -0,0 +1,91 @@
+use crate::HodecoMap;
+
+/// A map from coordinates in compressed space to coordinates in decompressed space.
+///
+/// [hodeco_paf_line](crate::hodeco_paf_line) and the other functions of this crate only access the maps through this trait,
+/// so other compression schemes can be decompressed by implementing it.
+/// The map must be monotonic, i.e. `expand(i) <= expand(i + 1)`,
+/// and compressed position `i` must expand into the decompressed characters `expand(i)..expand(i + 1)`.
+pub trait CoordinateMap {
+    /// Returns the decompressed coordinate of the given compressed coordinate.
+    ///
+    /// Valid coordinates range from zero to [CoordinateMap::total_compressed] inclusively,
+    /// implementations may panic for other coordinates.
+    fn expand(&self, compressed: usize) -> usize;
+
+    /// Returns the decompressed length of the sequence.
+    fn total_expanded(&self) -> usize;
+
+    /// Returns the compressed length of the sequence.
+    fn total_compressed(&self) -> usize;
+}
+
+impl CoordinateMap for HodecoMap {
+    #[inline]
+    fn expand(&self, compressed: usize) -> usize {
+        self.get(compressed)
+    }
+
+    fn total_expanded(&self) -> usize {
+        self.decompressed_length()
+    }
+
+    fn total_compressed(&self) -> usize {
+        self.len().saturating_sub(1)
+    }
+}
+
+/// Returns the compressed coordinate whose run contains the given decompressed coordinate,
+/// i.e. the inverse of [CoordinateMap::expand].
+///
+/// This is the largest compressed coordinate `i` with `map.expand(i) <= decompressed`,
+/// found by binary search since the map is monotonic.
+/// At run boundaries, the decompressed coordinate belongs to the run that starts there,
+/// so `compress_coordinate(map, map.expand(i)) == i` for all `i` unless the run of `i` is empty,
+/// i.e. `map.expand(i + 1) == map.expand(i)`, in which case the next non-empty run is returned.
+/// The decompressed length maps to the compressed length,
+/// and larger decompressed coordinates are clamped to the compressed length.
+pub fn compress_coordinate<Map: CoordinateMap + ?Sized>(map: &Map, decompressed: usize) -> usize {
+    // Search the first compressed coordinate after the result, which is the first one that expands beyond.
+    let mut low = 1;
+    let mut high = map.total_compressed() + 1;
+    while low < high {
+        let middle = low + (high - low) / 2;
+        if map.expand(middle) <= decompressed {
+            low = middle + 1;
+        } else {
+            high = middle;
+        }
+    }
+    low - 1
+}
+
+impl CoordinateMap for [usize] {
+    #[inline]
+    fn expand(&self, compressed: usize) -> usize {
+        self[compressed]
+    }
+
+    fn total_expanded(&self) -> usize {
+        self[self.len() - 1]
+    }
+
+    fn total_compressed(&self) -> usize {
+        self.len().saturating_sub(1)
+    }
+}
+
+impl CoordinateMap for Vec<usize> {
+    #[inline]
+    fn expand(&self, compressed: usize) -> usize {
+        self[compressed]
+    }
+
+    fn total_expanded(&self) -> usize {
+        self[self.len() - 1]
+    }
+
+    fn total_compressed(&self) -> usize {
+        self.len().saturating_sub(1)
+    }
+}
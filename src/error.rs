@@ -0,0 +1,380 @@
+use std::fmt::{Display, Formatter};
+
+/// The role of a sequence in an alignment.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SequenceRole {
+    /// The query sequence, i.e. the read.
+    Query,
+    /// The target sequence, i.e. the reference.
+    Target,
+}
+
+/// An error that occurred while homopolymer decompressing a PAF line.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum HodecoError {
+    /// No hodeco map was found for a sequence.
+    MissingMap {
+        /// The role of the sequence.
+        role: SequenceRole,
+        /// The name of the sequence.
+        sequence_name: String,
+    },
+
+    /// The sequence length stated in the PAF line does not match the compressed length of the hodeco map.
+    SequenceLengthMismatch {
+        /// The role of the sequence.
+        role: SequenceRole,
+        /// The name of the sequence.
+        sequence_name: String,
+        /// The compressed sequence length stated in the PAF line.
+        paf_length: usize,
+        /// The compressed sequence length according to the hodeco map.
+        map_length: usize,
+    },
+
+    /// A decompressed coordinate lies beyond the decompressed length of its sequence,
+    /// which indicates a corrupt hodeco map.
+    CoordinateOutOfBounds {
+        /// The role of the sequence.
+        role: SequenceRole,
+        /// The name of the sequence.
+        sequence_name: String,
+        /// The decompressed coordinate.
+        coordinate: usize,
+        /// The decompressed length of the sequence.
+        decompressed_length: usize,
+    },
+
+    /// The alignment or a column of its alignment string reaches beyond the end of the compressed sequence,
+    /// so it cannot be decompressed with the hodeco map.
+    CoordinateOutOfRange {
+        /// The role of the sequence.
+        role: SequenceRole,
+        /// The name of the sequence.
+        sequence_name: String,
+        /// The compressed start offset of the alignment or column.
+        start: usize,
+        /// The compressed end offset of the alignment or column.
+        end: usize,
+        /// The compressed length of the sequence according to the hodeco map.
+        compressed_length: usize,
+    },
+
+    /// The hodeco map assigns no decompressed characters to a compressed position of a sequence,
+    /// so a mismatch at that position cannot be decompressed.
+    EmptyRun {
+        /// The role of the sequence.
+        role: SequenceRole,
+        /// The name of the sequence.
+        sequence_name: String,
+        /// The compressed position with the empty run.
+        position: usize,
+    },
+
+    /// The decompressed alignment does not span any characters of a sequence.
+    EmptyAlignment {
+        /// The role of the sequence.
+        role: SequenceRole,
+        /// The name of the sequence.
+        sequence_name: String,
+    },
+
+    /// The decompressed alignment spans fewer characters of a sequence than the compressed alignment,
+    /// which indicates a corrupt hodeco map.
+    ShrunkAlignment {
+        /// The role of the sequence.
+        role: SequenceRole,
+        /// The name of the sequence.
+        sequence_name: String,
+        /// The compressed start and end coordinates of the alignment.
+        compressed_range: (usize, usize),
+        /// The decompressed start and end coordinates of the alignment.
+        decompressed_range: (usize, usize),
+    },
+
+    /// The difference string contains an insertion or deletion without characters.
+    EmptyIndel {
+        /// The name of the query sequence.
+        query_sequence_name: String,
+    },
+
+    /// The query characters of the decompressed difference string do not add up to the query coordinate range.
+    DifferenceStringLengthMismatch {
+        /// The name of the query sequence.
+        query_sequence_name: String,
+        /// The number of query characters in the difference string.
+        difference_string_length: usize,
+        /// The length of the query coordinate range.
+        coordinate_length: usize,
+    },
+
+    /// Homopolymer compressing the decompressed difference string does not reproduce the original difference string.
+    DifferenceStringRoundtripMismatch {
+        /// The name of the query sequence.
+        query_sequence_name: String,
+        /// The index of the first differing column of the original difference string,
+        /// counting adjacent matches as a single column.
+        column_index: usize,
+    },
+
+    /// The difference string contains a character that is not an ASCII nucleotide code.
+    /// Decompression assumes that each character corresponds to one compressed position,
+    /// so such characters would misalign the hodeco map.
+    InvalidDifferenceCharacter {
+        /// The name of the query sequence.
+        query_sequence_name: String,
+        /// The invalid character.
+        character: char,
+    },
+
+    /// The decompressed difference string would have more individually stored columns than allowed.
+    ExpansionTooLarge {
+        /// The name of the query sequence.
+        query_sequence_name: String,
+        /// The number of individually stored columns of the decompressed difference string.
+        expanded_columns: usize,
+        /// The maximum allowed number of individually stored columns.
+        max_expanded_columns: usize,
+    },
+
+    /// The PAF line has neither a CIGAR string nor a difference string,
+    /// so its base counts cannot be recomputed.
+    MissingAlignmentString {
+        /// The name of the query sequence.
+        query_sequence_name: String,
+    },
+
+    /// The CIGAR string contains a mismatch column, which is not supported.
+    MismatchInCigar {
+        /// The name of the query sequence.
+        query_sequence_name: String,
+    },
+
+    /// An optional tag that should be remapped is missing.
+    MissingTag {
+        /// The name of the query sequence.
+        query_sequence_name: String,
+        /// The name of the tag, e.g. `zs`.
+        tag: String,
+    },
+
+    /// An optional tag that should be remapped is not an integer tag
+    /// or does not hold a valid compressed coordinate of its sequence.
+    InvalidCoordinateTag {
+        /// The name of the query sequence.
+        query_sequence_name: String,
+        /// The tag as found in the PAF line, e.g. `zs:i:12`.
+        field: String,
+    },
+}
+
+impl HodecoError {
+    /// Returns a short identifier of the kind of this error, e.g. `missing-map`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            HodecoError::MissingMap { .. } => "missing-map",
+            HodecoError::SequenceLengthMismatch { .. } => "sequence-length-mismatch",
+            HodecoError::CoordinateOutOfBounds { .. } => "coordinate-out-of-bounds",
+            HodecoError::CoordinateOutOfRange { .. } => "coordinate-out-of-range",
+            HodecoError::EmptyRun { .. } => "empty-run",
+            HodecoError::EmptyAlignment { .. } => "empty-alignment",
+            HodecoError::ShrunkAlignment { .. } => "shrunk-alignment",
+            HodecoError::EmptyIndel { .. } => "empty-indel",
+            HodecoError::DifferenceStringLengthMismatch { .. } => {
+                "difference-string-length-mismatch"
+            }
+            HodecoError::DifferenceStringRoundtripMismatch { .. } => {
+                "difference-string-roundtrip-mismatch"
+            }
+            HodecoError::InvalidDifferenceCharacter { .. } => "invalid-difference-character",
+            HodecoError::ExpansionTooLarge { .. } => "expansion-too-large",
+            HodecoError::MissingAlignmentString { .. } => "missing-alignment-string",
+            HodecoError::MismatchInCigar { .. } => "mismatch-in-cigar",
+            HodecoError::MissingTag { .. } => "missing-tag",
+            HodecoError::InvalidCoordinateTag { .. } => "invalid-coordinate-tag",
+        }
+    }
+
+    /// Returns the name of the sequence this error is about.
+    /// This is the query sequence unless the error has a [SequenceRole].
+    pub fn sequence_name(&self) -> &str {
+        match self {
+            HodecoError::MissingMap { sequence_name, .. }
+            | HodecoError::SequenceLengthMismatch { sequence_name, .. }
+            | HodecoError::CoordinateOutOfBounds { sequence_name, .. }
+            | HodecoError::CoordinateOutOfRange { sequence_name, .. }
+            | HodecoError::EmptyRun { sequence_name, .. }
+            | HodecoError::EmptyAlignment { sequence_name, .. }
+            | HodecoError::ShrunkAlignment { sequence_name, .. } => sequence_name,
+            HodecoError::EmptyIndel {
+                query_sequence_name,
+            }
+            | HodecoError::DifferenceStringLengthMismatch {
+                query_sequence_name,
+                ..
+            }
+            | HodecoError::DifferenceStringRoundtripMismatch {
+                query_sequence_name,
+                ..
+            }
+            | HodecoError::InvalidDifferenceCharacter {
+                query_sequence_name,
+                ..
+            }
+            | HodecoError::ExpansionTooLarge {
+                query_sequence_name,
+                ..
+            }
+            | HodecoError::MissingAlignmentString {
+                query_sequence_name,
+            }
+            | HodecoError::MismatchInCigar {
+                query_sequence_name,
+            }
+            | HodecoError::MissingTag {
+                query_sequence_name,
+                ..
+            }
+            | HodecoError::InvalidCoordinateTag {
+                query_sequence_name,
+                ..
+            } => query_sequence_name,
+        }
+    }
+}
+
+impl Display for SequenceRole {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SequenceRole::Query => write!(f, "query"),
+            SequenceRole::Target => write!(f, "target"),
+        }
+    }
+}
+
+impl Display for HodecoError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HodecoError::MissingMap {
+                role,
+                sequence_name,
+            } => write!(f, "{role} hodeco map not found: {sequence_name}"),
+            HodecoError::SequenceLengthMismatch {
+                role,
+                sequence_name,
+                paf_length,
+                map_length,
+            } => write!(
+                f,
+                "{role} sequence {sequence_name} has length {paf_length} in the PAF line, but length {map_length} in the hodeco map"
+            ),
+            HodecoError::CoordinateOutOfBounds {
+                role,
+                sequence_name,
+                coordinate,
+                decompressed_length,
+            } => write!(
+                f,
+                "decompressed coordinate {coordinate} is out of bounds of {role} sequence {sequence_name} with decompressed length {decompressed_length}"
+            ),
+            HodecoError::CoordinateOutOfRange {
+                role,
+                sequence_name,
+                start,
+                end,
+                compressed_length,
+            } => write!(
+                f,
+                "alignment range from compressed offset {start} to {end} is out of range of {role} sequence {sequence_name} with compressed length {compressed_length}"
+            ),
+            HodecoError::EmptyRun {
+                role,
+                sequence_name,
+                position,
+            } => write!(
+                f,
+                "compressed position {position} of {role} sequence {sequence_name} has an empty run in the hodeco map"
+            ),
+            HodecoError::EmptyAlignment {
+                role,
+                sequence_name,
+            } => write!(
+                f,
+                "decompressed alignment is empty on {role} sequence {sequence_name}"
+            ),
+            HodecoError::ShrunkAlignment {
+                role,
+                sequence_name,
+                compressed_range: (compressed_start, compressed_end),
+                decompressed_range: (decompressed_start, decompressed_end),
+            } => write!(
+                f,
+                "decompressed alignment from {decompressed_start} to {decompressed_end} on {role} sequence {sequence_name} is shorter than the compressed alignment from {compressed_start} to {compressed_end}"
+            ),
+            HodecoError::EmptyIndel {
+                query_sequence_name,
+            } => write!(
+                f,
+                "empty insertion or deletion in difference string of query {query_sequence_name}"
+            ),
+            HodecoError::DifferenceStringLengthMismatch {
+                query_sequence_name,
+                difference_string_length,
+                coordinate_length,
+            } => write!(
+                f,
+                "difference string of query {query_sequence_name} spans {difference_string_length} query characters, but the query coordinates span {coordinate_length}"
+            ),
+            HodecoError::DifferenceStringRoundtripMismatch {
+                query_sequence_name,
+                column_index,
+            } => write!(
+                f,
+                "recompressing the difference string of query {query_sequence_name} does not reproduce column {column_index} of the original difference string"
+            ),
+            HodecoError::InvalidDifferenceCharacter {
+                query_sequence_name,
+                character,
+            } => write!(
+                f,
+                "difference string of query {query_sequence_name} contains {character:?}, which is not an ASCII nucleotide code"
+            ),
+            HodecoError::ExpansionTooLarge {
+                query_sequence_name,
+                expanded_columns,
+                max_expanded_columns,
+            } => write!(
+                f,
+                "difference string of query {query_sequence_name} would expand to {expanded_columns} columns, more than the maximum of {max_expanded_columns}"
+            ),
+            HodecoError::MissingAlignmentString {
+                query_sequence_name,
+            } => write!(
+                f,
+                "alignment of query {query_sequence_name} has neither a CIGAR string nor a difference string"
+            ),
+            HodecoError::MismatchInCigar {
+                query_sequence_name,
+            } => write!(
+                f,
+                "mismatch not supported in CIGAR of query {query_sequence_name}"
+            ),
+            HodecoError::MissingTag {
+                query_sequence_name,
+                tag,
+            } => write!(
+                f,
+                "alignment of query {query_sequence_name} has no tag {tag}"
+            ),
+            HodecoError::InvalidCoordinateTag {
+                query_sequence_name,
+                field,
+            } => write!(
+                f,
+                "tag {field} of alignment of query {query_sequence_name} is not a valid compressed coordinate"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for HodecoError {}
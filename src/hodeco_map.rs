@@ -0,0 +1,225 @@
+use std::convert::TryFrom;
+use std::mem;
+
+/// The homopolymer decompression map of a single sequence.
+///
+/// Entry `i` is the decompressed offset of compressed position `i`.
+/// The map has one entry more than the compressed sequence has characters,
+/// the last one being the decompressed length of the sequence.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum HodecoMap {
+    /// Offsets stored as `u32`, for sequences with a decompressed length below 4Gbp.
+    Narrow(Vec<u32>),
+    /// Offsets stored as `usize`.
+    Wide(Vec<usize>),
+    /// Offsets stored as runs of positions whose offsets increase by exactly one,
+    /// i.e. runs of characters that were not compressed.
+    RunLength(RunLengthOffsets),
+}
+
+/// Run-length encoded offsets of a [HodecoMap].
+///
+/// Each run starts at a compressed position and covers all following positions whose offsets increase by one.
+/// Only the start position and start offset of each run are stored, so for sequences that are mostly
+/// incompressible this uses much less memory than storing all offsets.
+/// Lookups take logarithmic time in the number of runs.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RunLengthOffsets {
+    run_start_positions: Vec<usize>,
+    run_start_offsets: Vec<usize>,
+    len: usize,
+}
+
+impl RunLengthOffsets {
+    /// Run-length encodes the given offsets.
+    pub fn new(offsets: impl IntoIterator<Item = usize>) -> Self {
+        let mut run_start_positions = Vec::new();
+        let mut run_start_offsets = Vec::new();
+        let mut len = 0;
+        let mut previous_offset = None;
+
+        for offset in offsets {
+            if previous_offset.map(|previous_offset| previous_offset + 1) != Some(offset) {
+                run_start_positions.push(len);
+                run_start_offsets.push(offset);
+            }
+            previous_offset = Some(offset);
+            len += 1;
+        }
+
+        run_start_positions.shrink_to_fit();
+        run_start_offsets.shrink_to_fit();
+        Self {
+            run_start_positions,
+            run_start_offsets,
+            len,
+        }
+    }
+
+    /// Returns the decompressed offset of the given compressed position.
+    ///
+    /// Panics if the position is out of bounds.
+    pub fn get(&self, position: usize) -> usize {
+        assert!(
+            position < self.len,
+            "position {position} is out of bounds of run-length encoded offsets of length {}",
+            self.len
+        );
+        let run = self
+            .run_start_positions
+            .partition_point(|&run_start_position| run_start_position <= position)
+            - 1;
+        self.run_start_offsets[run] + (position - self.run_start_positions[run])
+    }
+}
+
+/// The representation of [HodecoMap]s in memory.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MapRepresentation {
+    /// Store all offsets, with the width given by [MapOffsetWidth].
+    Dense,
+    /// Store the offsets run-length encoded.
+    RunLength,
+    /// Store the offsets run-length encoded if that uses less memory than storing all offsets.
+    Smallest,
+}
+
+/// The integer width used to store the offsets of a [HodecoMap].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MapOffsetWidth {
+    /// Use 32 bits if all offsets fit, and 64 bits otherwise.
+    Auto,
+    /// Always use 32 bits.
+    Bits32,
+    /// Always use 64 bits.
+    Bits64,
+}
+
+impl From<Vec<usize>> for HodecoMap {
+    /// Wraps the given offsets without copying them.
+    fn from(offsets: Vec<usize>) -> Self {
+        Self::Wide(offsets)
+    }
+}
+
+impl HodecoMap {
+    /// Creates a hodeco map from the given offsets, stored with the given width.
+    ///
+    /// Returns `None` if the width is [MapOffsetWidth::Bits32] but the offsets do not fit into 32 bits.
+    pub fn from_offsets(offsets: Vec<usize>, width: MapOffsetWidth) -> Option<Self> {
+        // Check every offset, since a corrupt map may not be monotonic.
+        let fits_narrow = width != MapOffsetWidth::Bits64
+            && offsets.iter().all(|&offset| u32::try_from(offset).is_ok());
+
+        match width {
+            MapOffsetWidth::Auto | MapOffsetWidth::Bits32 if fits_narrow => Some(Self::Narrow(
+                offsets
+                    .into_iter()
+                    .map(|offset| u32::try_from(offset).unwrap())
+                    .collect(),
+            )),
+            MapOffsetWidth::Bits32 => None,
+            MapOffsetWidth::Auto | MapOffsetWidth::Bits64 => Some(Self::Wide(offsets)),
+        }
+    }
+
+    /// Returns the decompressed offset of the given compressed position.
+    ///
+    /// Panics if the position is out of bounds.
+    #[inline]
+    pub fn get(&self, position: usize) -> usize {
+        match self {
+            Self::Narrow(offsets) => offsets[position] as usize,
+            Self::Wide(offsets) => offsets[position],
+            Self::RunLength(offsets) => offsets.get(position),
+        }
+    }
+
+    /// Returns the number of entries of the map, which is one more than the compressed length of the sequence.
+    pub fn len(&self) -> usize {
+        match self {
+            Self::Narrow(offsets) => offsets.len(),
+            Self::Wide(offsets) => offsets.len(),
+            Self::RunLength(offsets) => offsets.len,
+        }
+    }
+
+    /// Returns true if the map has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the decompressed length of the sequence.
+    ///
+    /// Panics if the map is empty.
+    pub fn decompressed_length(&self) -> usize {
+        self.get(self.len() - 1)
+    }
+
+    /// Returns the number of bytes used by the offsets of this map.
+    pub fn size_in_bytes(&self) -> usize {
+        match self {
+            Self::Narrow(offsets) => offsets.len() * mem::size_of::<u32>(),
+            Self::Wide(offsets) => offsets.len() * mem::size_of::<usize>(),
+            Self::RunLength(offsets) => {
+                (offsets.run_start_positions.len() + offsets.run_start_offsets.len())
+                    * mem::size_of::<usize>()
+            }
+        }
+    }
+
+    /// Returns the number of bytes the offsets of this map would use when stored densely with the given width.
+    /// If the width is [MapOffsetWidth::Bits32] but the offsets do not fit into 32 bits, 64 bit offsets are assumed.
+    pub fn dense_size_in_bytes(&self, width: MapOffsetWidth) -> usize {
+        let fits_narrow = self.is_empty() || u32::try_from(self.decompressed_length()).is_ok();
+        if width != MapOffsetWidth::Bits64 && fits_narrow {
+            self.len() * mem::size_of::<u32>()
+        } else {
+            self.len() * mem::size_of::<usize>()
+        }
+    }
+
+    /// Converts this map into the dense representation with the given width.
+    ///
+    /// Returns `None` if the width is [MapOffsetWidth::Bits32] but the offsets do not fit into 32 bits.
+    pub fn into_dense(self, width: MapOffsetWidth) -> Option<Self> {
+        match (self, width) {
+            (map @ Self::Narrow(_), MapOffsetWidth::Auto | MapOffsetWidth::Bits32)
+            | (map @ Self::Wide(_), MapOffsetWidth::Bits64) => Some(map),
+            (map, width) => Self::from_offsets(
+                (0..map.len()).map(|position| map.get(position)).collect(),
+                width,
+            ),
+        }
+    }
+
+    /// Converts this map into the given representation.
+    /// Dense maps keep their offset width.
+    pub fn into_representation(self, representation: MapRepresentation) -> Self {
+        match (representation, self) {
+            (MapRepresentation::Dense, Self::RunLength(offsets)) => Self::Wide(
+                (0..offsets.len)
+                    .map(|position| offsets.get(position))
+                    .collect(),
+            ),
+            (MapRepresentation::RunLength, map @ (Self::Narrow(_) | Self::Wide(_))) => {
+                map.to_run_length()
+            }
+            (MapRepresentation::Smallest, map @ (Self::Narrow(_) | Self::Wide(_))) => {
+                let run_length = map.to_run_length();
+                if run_length.size_in_bytes() < map.size_in_bytes() {
+                    run_length
+                } else {
+                    map
+                }
+            }
+            (_, map) => map,
+        }
+    }
+
+    fn to_run_length(&self) -> Self {
+        Self::RunLength(RunLengthOffsets::new(
+            (0..self.len()).map(|position| self.get(position)),
+        ))
+    }
+}
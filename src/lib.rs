@@ -0,0 +1,1155 @@
+#![warn(missing_docs)]
+
+//! Transform minimap2 alignments in homopolymer compressed space to input space.
+//!
+//! The homopolymer decompression maps (hodeco maps) map each position in a compressed sequence
+//! to its position in the decompressed sequence.
+//! A hodeco map of a sequence with compressed length `n` has `n + 1` entries,
+//! the last one being the decompressed length of the sequence.
+
+mod coordinate_map;
+mod error;
+mod hodeco_map;
+mod paf_record;
+mod pipeline;
+mod split;
+
+pub use crate::coordinate_map::{compress_coordinate, CoordinateMap};
+pub use crate::error::{HodecoError, SequenceRole};
+pub use crate::hodeco_map::{HodecoMap, MapOffsetWidth, MapRepresentation, RunLengthOffsets};
+pub use crate::paf_record::{PafRecord, PafRecordReadError, PafRecordReader};
+pub use crate::pipeline::DecompressIter;
+pub use crate::split::split_on_gaps;
+use log::debug;
+use minimap2_paf_io::data::{AlignmentDifference, CigarColumn, DifferenceColumn, PAFLine};
+use std::collections::HashMap;
+
+/// Alignment statistics in homopolymer decompressed space.
+///
+/// Each field is `None` if the PAF line does not carry the data needed to recompute it.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct AlignmentStats {
+    /// The number of matching bases, recomputed from the CIGAR string.
+    pub number_of_matching_bases: Option<usize>,
+    /// The number of bases and gaps, recomputed from the CIGAR string.
+    pub number_of_bases_and_gaps: Option<usize>,
+    /// The total number of mismatches and gaps, recomputed from the difference string.
+    pub total_number_of_mismatches_and_gaps: Option<usize>,
+}
+
+/// Homopolymer decompresses a PAF line.
+///
+/// Coordinates, sequence lengths, the CIGAR string, the difference string and the statistics derived from them
+/// are translated from homopolymer compressed space into homopolymer decompressed space.
+///
+/// If the line has a difference string, the divergences are recomputed from the decompressed difference string,
+/// counting each gap character for `dv` and each gap as a single event for `de`.
+/// Otherwise, they are rescaled by the ratio of the decompressed and compressed query lengths.
+///
+/// The maps are usually [HodecoMap]s, but any [CoordinateMap] can be used.
+pub fn hodeco_paf_line<Map: CoordinateMap>(
+    mut hoco_paf: PAFLine,
+    query_hodeco_maps: &HashMap<String, Map>,
+    target_hodeco_maps: &HashMap<String, Map>,
+) -> Result<PAFLine, HodecoError> {
+    let (query_hodeco_map, target_hodeco_map) =
+        get_hodeco_maps(&hoco_paf, query_hodeco_maps, target_hodeco_maps)?;
+
+    // Captured before any field of the line is overwritten.
+    let hoco = CompressedValues::new(&hoco_paf);
+
+    check_alignment(&hoco_paf, query_hodeco_map, target_hodeco_map)?;
+    hoco_paf.query_sequence_length = query_hodeco_map.total_expanded();
+    hoco_paf.target_sequence_length = target_hodeco_map.total_expanded();
+
+    hoco_paf.query_start_coordinate = query_hodeco_map.expand(hoco.query_start_coordinate);
+    hoco_paf.query_end_coordinate = query_hodeco_map.expand(hoco.query_end_coordinate);
+    hoco_paf.target_start_coordinate_on_original_strand =
+        target_hodeco_map.expand(hoco.target_start_coordinate);
+    hoco_paf.target_end_coordinate_on_original_strand =
+        target_hodeco_map.expand(hoco.target_end_coordinate);
+
+    let query_alignment_length = hoco_paf.query_end_coordinate - hoco_paf.query_start_coordinate;
+    let target_alignment_length = hoco_paf.target_end_coordinate_on_original_strand
+        - hoco_paf.target_start_coordinate_on_original_strand;
+
+    if let Some(cigar_string) = &mut hoco_paf.cigar_string {
+        let mut number_of_matching_bases = 0;
+        let mut number_of_bases_and_gaps = 0;
+
+        let mut query_offset = hoco.query_start_coordinate;
+        let mut target_offset = hoco.target_start_coordinate;
+
+        for cigar_column in &mut cigar_string.0 {
+            *cigar_column = hodeco_cigar_column(
+                cigar_column,
+                &mut query_offset,
+                &mut target_offset,
+                query_hodeco_map,
+                target_hodeco_map,
+                &hoco_paf.query_sequence_name,
+                &hoco_paf.target_sequence_name,
+            )?;
+            count_cigar_column(
+                cigar_column,
+                &mut number_of_matching_bases,
+                &mut number_of_bases_and_gaps,
+            );
+        }
+
+        hoco_paf.number_of_matching_bases = number_of_matching_bases;
+        hoco_paf.number_of_bases_and_gaps = number_of_bases_and_gaps;
+    }
+
+    if let Some(difference_string) = &mut hoco_paf.difference_string {
+        let mut total_number_of_mismatches_and_gaps = 0;
+
+        let mut query_hodeco_len = 0;
+        let mut target_hodeco_len = 0;
+
+        let mut query_offset = hoco.query_start_coordinate;
+        let mut target_offset = hoco.target_start_coordinate;
+        let mut mismatch_insertion = Vec::new();
+
+        for (index, difference_column) in difference_string.0.iter_mut().enumerate() {
+            let (hodeco_difference_column, additional_mismatches) = hodeco_difference_column(
+                difference_column,
+                &mut query_offset,
+                &mut target_offset,
+                query_hodeco_map,
+                target_hodeco_map,
+                &hoco_paf.query_sequence_name,
+                &hoco_paf.target_sequence_name,
+            )?;
+            *difference_column = hodeco_difference_column;
+            count_difference_column(
+                difference_column,
+                additional_mismatches,
+                &mut total_number_of_mismatches_and_gaps,
+            );
+
+            match difference_column {
+                DifferenceColumn::Match { length } => {
+                    query_hodeco_len += *length;
+                    target_hodeco_len += *length;
+                }
+                DifferenceColumn::Deletion {
+                    missing_query_characters,
+                } => target_hodeco_len += missing_query_characters.len(),
+                DifferenceColumn::Insertion {
+                    superfluous_query_characters,
+                } => query_hodeco_len += superfluous_query_characters.len(),
+                DifferenceColumn::Mismatch { reference, query } => {
+                    mismatch_insertion.push((index, additional_mismatches, *reference, *query));
+                    query_hodeco_len += additional_mismatches;
+                    target_hodeco_len += additional_mismatches;
+                }
+            }
+        }
+
+        for (index, hodeco_count, reference, query) in mismatch_insertion.into_iter().rev() {
+            for _ in 0..hodeco_count {
+                difference_string
+                    .0
+                    .insert(index, DifferenceColumn::Mismatch { reference, query });
+            }
+        }
+
+        hoco_paf.total_number_of_mismatches_and_gaps = Some(total_number_of_mismatches_and_gaps);
+        // assert_eq!(query_hodeco_len, hoco_paf.query_sequence_length);
+        // assert_eq!(target_hodeco_len, hoco_paf.target_sequence_length);
+        debug!(
+            "query difference length: {}, query expected length: {}",
+            query_hodeco_len, query_alignment_length
+        );
+        debug!(
+            "target difference length: {}, target expected length: {}",
+            target_hodeco_len, target_alignment_length,
+        );
+    }
+
+    if let Some(difference_string) = &hoco_paf.difference_string {
+        // Insertions and deletions expand differently than matches,
+        // so the divergences are recomputed rather than rescaled.
+        let (approximate_divergence, gap_compressed_divergence) =
+            difference_string_divergences(difference_string);
+        if let Some(approximate_per_base_sequence_divergence) =
+            &mut hoco_paf.approximate_per_base_sequence_divergence
+        {
+            *approximate_per_base_sequence_divergence = approximate_divergence;
+        }
+        if let Some(gap_compressed_per_base_sequence_divergence) =
+            &mut hoco_paf.gap_compressed_per_base_sequence_divergence
+        {
+            *gap_compressed_per_base_sequence_divergence = gap_compressed_divergence;
+        }
+    } else {
+        if let Some(approximate_per_base_sequence_divergence) =
+            &mut hoco_paf.approximate_per_base_sequence_divergence
+        {
+            *approximate_per_base_sequence_divergence *=
+                hoco_paf.query_sequence_length as f64 / hoco.query_sequence_length as f64;
+        }
+        if let Some(gap_compressed_per_base_sequence_divergence) =
+            &mut hoco_paf.gap_compressed_per_base_sequence_divergence
+        {
+            *gap_compressed_per_base_sequence_divergence *=
+                hoco_paf.query_sequence_length as f64 / hoco.query_sequence_length as f64;
+        }
+    }
+
+    Ok(hoco_paf)
+}
+
+/// The values of a PAF line in homopolymer compressed space that are needed while decompressing it.
+///
+/// [hodeco_paf_line] decompresses the line in place, so these are copied out
+/// before any of them is overwritten, and are only read from here afterwards.
+struct CompressedValues {
+    query_sequence_length: usize,
+    query_start_coordinate: usize,
+    query_end_coordinate: usize,
+    target_start_coordinate: usize,
+    target_end_coordinate: usize,
+}
+
+impl CompressedValues {
+    fn new(hoco_paf: &PAFLine) -> Self {
+        Self {
+            query_sequence_length: hoco_paf.query_sequence_length,
+            query_start_coordinate: hoco_paf.query_start_coordinate,
+            query_end_coordinate: hoco_paf.query_end_coordinate,
+            target_start_coordinate: hoco_paf.target_start_coordinate_on_original_strand,
+            target_end_coordinate: hoco_paf.target_end_coordinate_on_original_strand,
+        }
+    }
+}
+
+/// Homopolymer decompresses the coordinates stored in an integer-valued optional tag of a PAF line, e.g. `zs:i:12`.
+///
+/// The coordinates are remapped through the hodeco map of the query or the target sequence of the line,
+/// depending on `role`.
+/// Only tags that are unknown to the PAF parser can be remapped, and all occurrences of the tag are remapped.
+/// The other fields of the line are not changed, so this can be called before or after [hodeco_paf_line].
+pub fn hodeco_coordinate_tag<Map: CoordinateMap>(
+    paf: &mut PAFLine,
+    tag: &str,
+    role: SequenceRole,
+    query_hodeco_maps: &HashMap<String, Map>,
+    target_hodeco_maps: &HashMap<String, Map>,
+) -> Result<(), HodecoError> {
+    let (query_hodeco_map, target_hodeco_map) =
+        get_hodeco_maps(paf, query_hodeco_maps, target_hodeco_maps)?;
+    let hodeco_map = match role {
+        SequenceRole::Query => query_hodeco_map,
+        SequenceRole::Target => target_hodeco_map,
+    };
+
+    let mut found = false;
+    for field in &mut paf.unknown_fields {
+        let value = if let Some(value) = field
+            .strip_prefix(tag)
+            .and_then(|field| field.strip_prefix(':'))
+        {
+            value
+        } else {
+            continue;
+        };
+        found = true;
+
+        let coordinate = value
+            .strip_prefix("i:")
+            .and_then(|coordinate| coordinate.parse::<usize>().ok())
+            .filter(|&coordinate| coordinate <= hodeco_map.total_compressed())
+            .ok_or_else(|| HodecoError::InvalidCoordinateTag {
+                query_sequence_name: paf.query_sequence_name.clone(),
+                field: field.clone(),
+            })?;
+        *field = format!("{tag}:i:{}", hodeco_map.expand(coordinate));
+    }
+
+    if found {
+        Ok(())
+    } else {
+        Err(HodecoError::MissingTag {
+            query_sequence_name: paf.query_sequence_name.clone(),
+            tag: tag.to_string(),
+        })
+    }
+}
+
+/// Computes the alignment statistics of a PAF line in homopolymer decompressed space, without decompressing the line itself.
+///
+/// The statistics are the same as the ones written into the line by [hodeco_paf_line],
+/// and the line is validated the same way, so both fail for the same lines.
+pub fn compute_decompressed_stats<Map: CoordinateMap>(
+    hoco_paf: &PAFLine,
+    query_hodeco_maps: &HashMap<String, Map>,
+    target_hodeco_maps: &HashMap<String, Map>,
+) -> Result<AlignmentStats, HodecoError> {
+    let (query_hodeco_map, target_hodeco_map) =
+        get_hodeco_maps(hoco_paf, query_hodeco_maps, target_hodeco_maps)?;
+    check_alignment(hoco_paf, query_hodeco_map, target_hodeco_map)?;
+    let mut alignment_stats = AlignmentStats::default();
+
+    if let Some(cigar_string) = &hoco_paf.cigar_string {
+        let mut number_of_matching_bases = 0;
+        let mut number_of_bases_and_gaps = 0;
+
+        let mut query_offset = hoco_paf.query_start_coordinate;
+        let mut target_offset = hoco_paf.target_start_coordinate_on_original_strand;
+
+        for cigar_column in &cigar_string.0 {
+            let cigar_column = hodeco_cigar_column(
+                cigar_column,
+                &mut query_offset,
+                &mut target_offset,
+                query_hodeco_map,
+                target_hodeco_map,
+                &hoco_paf.query_sequence_name,
+                &hoco_paf.target_sequence_name,
+            )?;
+            count_cigar_column(
+                &cigar_column,
+                &mut number_of_matching_bases,
+                &mut number_of_bases_and_gaps,
+            );
+        }
+
+        alignment_stats.number_of_matching_bases = Some(number_of_matching_bases);
+        alignment_stats.number_of_bases_and_gaps = Some(number_of_bases_and_gaps);
+    }
+
+    if let Some(difference_string) = &hoco_paf.difference_string {
+        let mut total_number_of_mismatches_and_gaps = 0;
+
+        let mut query_offset = hoco_paf.query_start_coordinate;
+        let mut target_offset = hoco_paf.target_start_coordinate_on_original_strand;
+
+        for difference_column in &difference_string.0 {
+            let (difference_column, additional_mismatches) = hodeco_difference_column(
+                difference_column,
+                &mut query_offset,
+                &mut target_offset,
+                query_hodeco_map,
+                target_hodeco_map,
+                &hoco_paf.query_sequence_name,
+                &hoco_paf.target_sequence_name,
+            )?;
+            count_difference_column(
+                &difference_column,
+                additional_mismatches,
+                &mut total_number_of_mismatches_and_gaps,
+            );
+        }
+
+        alignment_stats.total_number_of_mismatches_and_gaps =
+            Some(total_number_of_mismatches_and_gaps);
+    }
+
+    Ok(alignment_stats)
+}
+
+/// Computes the number of individually stored columns of the difference string of a PAF line after decompression,
+/// without decompressing it.
+///
+/// These are the mismatches and the inserted and deleted characters.
+/// Matches are stored as a length and therefore not counted.
+/// Lines without difference string have no such columns.
+pub fn expanded_difference_string_columns<Map: CoordinateMap>(
+    hoco_paf: &PAFLine,
+    query_hodeco_maps: &HashMap<String, Map>,
+    target_hodeco_maps: &HashMap<String, Map>,
+) -> Result<usize, HodecoError> {
+    let difference_string = if let Some(difference_string) = &hoco_paf.difference_string {
+        difference_string
+    } else {
+        return Ok(0);
+    };
+    let (query_hodeco_map, target_hodeco_map) =
+        get_hodeco_maps(hoco_paf, query_hodeco_maps, target_hodeco_maps)?;
+    check_sequence_length(
+        SequenceRole::Query,
+        &hoco_paf.query_sequence_name,
+        hoco_paf.query_sequence_length,
+        query_hodeco_map,
+    )?;
+    check_sequence_length(
+        SequenceRole::Target,
+        &hoco_paf.target_sequence_name,
+        hoco_paf.target_sequence_length,
+        target_hodeco_map,
+    )?;
+
+    let mut expanded_columns = 0;
+    let mut query_offset = hoco_paf.query_start_coordinate;
+    let mut target_offset = hoco_paf.target_start_coordinate_on_original_strand;
+    for difference_column in &difference_string.0 {
+        check_difference_characters(difference_column, &hoco_paf.query_sequence_name)?;
+        match difference_column {
+            DifferenceColumn::Match { length } => {
+                query_offset += length;
+                target_offset += length;
+            }
+            DifferenceColumn::Mismatch { .. } => {
+                expanded_columns += expand_range(
+                    SequenceRole::Query,
+                    &hoco_paf.query_sequence_name,
+                    query_hodeco_map,
+                    query_offset,
+                    query_offset + 1,
+                )?;
+                check_range(
+                    SequenceRole::Target,
+                    &hoco_paf.target_sequence_name,
+                    target_hodeco_map,
+                    target_offset,
+                    target_offset + 1,
+                )?;
+                query_offset += 1;
+                target_offset += 1;
+            }
+            DifferenceColumn::Insertion {
+                superfluous_query_characters,
+            } => {
+                let query_limit = query_offset + superfluous_query_characters.len();
+                expanded_columns += expand_range(
+                    SequenceRole::Query,
+                    &hoco_paf.query_sequence_name,
+                    query_hodeco_map,
+                    query_offset,
+                    query_limit,
+                )?;
+                query_offset = query_limit;
+            }
+            DifferenceColumn::Deletion {
+                missing_query_characters,
+            } => {
+                let target_limit = target_offset + missing_query_characters.len();
+                expanded_columns += expand_range(
+                    SequenceRole::Target,
+                    &hoco_paf.target_sequence_name,
+                    target_hodeco_map,
+                    target_offset,
+                    target_limit,
+                )?;
+                target_offset = target_limit;
+            }
+        }
+    }
+
+    Ok(expanded_columns)
+}
+
+/// Checks that the query characters of the difference string of a decompressed PAF line
+/// add up to the length of its query coordinate range.
+///
+/// Lines without difference string are always consistent.
+pub fn check_difference_string_consistency(hodeco_paf: &PAFLine) -> Result<(), HodecoError> {
+    if let Some(difference_string) = &hodeco_paf.difference_string {
+        let difference_string_length: usize = difference_string
+            .0
+            .iter()
+            .map(|difference_column| match difference_column {
+                DifferenceColumn::Match { length } => *length,
+                DifferenceColumn::Mismatch { .. } => 1,
+                DifferenceColumn::Insertion {
+                    superfluous_query_characters,
+                } => superfluous_query_characters.len(),
+                DifferenceColumn::Deletion { .. } => 0,
+            })
+            .sum();
+        let coordinate_length = hodeco_paf.query_end_coordinate - hodeco_paf.query_start_coordinate;
+
+        if difference_string_length != coordinate_length {
+            return Err(HodecoError::DifferenceStringLengthMismatch {
+                query_sequence_name: hodeco_paf.query_sequence_name.clone(),
+                difference_string_length,
+                coordinate_length,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that homopolymer compressing the difference string of a decompressed PAF line
+/// using the hodeco maps reproduces the difference string of the original compressed line.
+///
+/// This verifies that decompressing the difference string was exact,
+/// in particular the duplication of mismatches and the expansion of insertions and deletions.
+/// Adjacent matches are merged before comparing.
+/// Lines without difference string are always consistent.
+pub fn check_difference_string_roundtrip<Map: CoordinateMap>(
+    hoco_difference_string: Option<&AlignmentDifference>,
+    hodeco_paf: &PAFLine,
+    query_hodeco_maps: &HashMap<String, Map>,
+    target_hodeco_maps: &HashMap<String, Map>,
+) -> Result<(), HodecoError> {
+    let (hoco_difference_string, hodeco_difference_string) =
+        match (hoco_difference_string, &hodeco_paf.difference_string) {
+            (Some(hoco_difference_string), Some(hodeco_difference_string)) => {
+                (hoco_difference_string, hodeco_difference_string)
+            }
+            _ => return Ok(()),
+        };
+    let (query_hodeco_map, target_hodeco_map) =
+        get_hodeco_maps(hodeco_paf, query_hodeco_maps, target_hodeco_maps)?;
+
+    let mut expected = Vec::new();
+    for difference_column in &hoco_difference_string.0 {
+        push_merging_matches(&mut expected, difference_column.clone());
+    }
+    let recompressed = recompress_difference_string(
+        hodeco_difference_string,
+        compress_coordinate(query_hodeco_map, hodeco_paf.query_start_coordinate),
+        compress_coordinate(
+            target_hodeco_map,
+            hodeco_paf.target_start_coordinate_on_original_strand,
+        ),
+        query_hodeco_map,
+        target_hodeco_map,
+    );
+
+    let (recompressed, failed_column_index) = match recompressed {
+        Ok(recompressed) => (recompressed, None),
+        Err((recompressed, column_index)) => (recompressed, Some(column_index)),
+    };
+    let column_index = expected
+        .iter()
+        .zip(&recompressed)
+        .position(|(expected, recompressed)| expected != recompressed)
+        .or(failed_column_index)
+        .or_else(|| {
+            if expected.len() == recompressed.len() {
+                None
+            } else {
+                Some(expected.len().min(recompressed.len()))
+            }
+        });
+    if let Some(column_index) = column_index {
+        Err(HodecoError::DifferenceStringRoundtripMismatch {
+            query_sequence_name: hodeco_paf.query_sequence_name.clone(),
+            column_index,
+        })
+    } else {
+        Ok(())
+    }
+}
+
+/// Homopolymer compresses a decompressed difference string that starts at the given compressed offsets.
+/// If the difference string does not align with the homopolymer runs of the hodeco maps,
+/// returns the columns compressed so far and the index of the compressed column at which this happens.
+fn recompress_difference_string(
+    hodeco_difference_string: &AlignmentDifference,
+    mut query_offset: usize,
+    mut target_offset: usize,
+    query_hodeco_map: &impl CoordinateMap,
+    target_hodeco_map: &impl CoordinateMap,
+) -> Result<Vec<DifferenceColumn>, (Vec<DifferenceColumn>, usize)> {
+    let mut result = Vec::new();
+    let mut columns = hodeco_difference_string.0.iter();
+    while let Some(difference_column) = columns.next() {
+        // A match is merged into a preceding match, so it belongs to the same compressed column.
+        let column_index = match (difference_column, result.last()) {
+            (DifferenceColumn::Match { .. }, Some(DifferenceColumn::Match { .. })) => {
+                result.len() - 1
+            }
+            _ => result.len(),
+        };
+        match difference_column {
+            DifferenceColumn::Match { length } => {
+                let hodeco_limit = query_hodeco_map.expand(query_offset) + length;
+                let query_limit = compress_coordinate(query_hodeco_map, hodeco_limit);
+                let compressed_length = query_limit - query_offset;
+                if query_hodeco_map.expand(query_limit) != hodeco_limit
+                    || target_offset + compressed_length > target_hodeco_map.total_compressed()
+                {
+                    return Err((result, column_index));
+                }
+                push_merging_matches(
+                    &mut result,
+                    DifferenceColumn::Match {
+                        length: compressed_length,
+                    },
+                );
+                query_offset = query_limit;
+                target_offset += compressed_length;
+            }
+            DifferenceColumn::Mismatch { .. } => {
+                if query_offset >= query_hodeco_map.total_compressed()
+                    || target_offset >= target_hodeco_map.total_compressed()
+                {
+                    return Err((result, column_index));
+                }
+                // The mismatch is repeated for each character of the query homopolymer run.
+                for _ in 1..run_length(query_hodeco_map, query_offset) {
+                    if columns.next() != Some(difference_column) {
+                        return Err((result, column_index));
+                    }
+                }
+                result.push(difference_column.clone());
+                query_offset += 1;
+                target_offset += 1;
+            }
+            DifferenceColumn::Insertion {
+                superfluous_query_characters,
+            } => {
+                if let Some(superfluous_query_characters) = recompress_string(
+                    superfluous_query_characters,
+                    query_hodeco_map,
+                    &mut query_offset,
+                ) {
+                    result.push(DifferenceColumn::Insertion {
+                        superfluous_query_characters,
+                    });
+                } else {
+                    return Err((result, column_index));
+                }
+            }
+            DifferenceColumn::Deletion {
+                missing_query_characters,
+            } => {
+                if let Some(missing_query_characters) = recompress_string(
+                    missing_query_characters,
+                    target_hodeco_map,
+                    &mut target_offset,
+                ) {
+                    result.push(DifferenceColumn::Deletion {
+                        missing_query_characters,
+                    });
+                } else {
+                    return Err((result, column_index));
+                }
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// Homopolymer compresses a string that was decompressed by [homopolymer_decompress_string] starting at the given offset,
+/// and advances the offset past it.
+/// Returns `None` if the string does not align with the homopolymer runs of the hodeco map.
+fn recompress_string(
+    input: &str,
+    hodeco_map: &impl CoordinateMap,
+    offset: &mut usize,
+) -> Option<String> {
+    let mut result = String::new();
+    let mut characters = input.chars();
+    while let Some(character) = characters.next() {
+        if *offset >= hodeco_map.total_compressed() {
+            return None;
+        }
+        for _ in 1..run_length(hodeco_map, *offset) {
+            if characters.next() != Some(character) {
+                return None;
+            }
+        }
+        result.push(character);
+        *offset += 1;
+    }
+    Some(result)
+}
+
+/// Returns the length of the homopolymer run at the given compressed offset.
+fn run_length(hodeco_map: &impl CoordinateMap, offset: usize) -> usize {
+    hodeco_map.expand(offset + 1) - hodeco_map.expand(offset)
+}
+
+/// Appends a difference column, merging it into the last column if both are matches.
+fn push_merging_matches(columns: &mut Vec<DifferenceColumn>, column: DifferenceColumn) {
+    if let (
+        Some(DifferenceColumn::Match { length }),
+        DifferenceColumn::Match {
+            length: additional_length,
+        },
+    ) = (columns.last_mut(), &column)
+    {
+        *length += additional_length;
+    } else {
+        columns.push(column);
+    }
+}
+
+fn get_hodeco_maps<'maps, Map>(
+    hoco_paf: &PAFLine,
+    query_hodeco_maps: &'maps HashMap<String, Map>,
+    target_hodeco_maps: &'maps HashMap<String, Map>,
+) -> Result<(&'maps Map, &'maps Map), HodecoError> {
+    let query_hodeco_map = query_hodeco_maps
+        .get(&hoco_paf.query_sequence_name)
+        .ok_or_else(|| HodecoError::MissingMap {
+            role: SequenceRole::Query,
+            sequence_name: hoco_paf.query_sequence_name.clone(),
+        })?;
+    let target_hodeco_map = target_hodeco_maps
+        .get(&hoco_paf.target_sequence_name)
+        .ok_or_else(|| HodecoError::MissingMap {
+            role: SequenceRole::Target,
+            sequence_name: hoco_paf.target_sequence_name.clone(),
+        })?;
+    Ok((query_hodeco_map, target_hodeco_map))
+}
+
+fn check_sequence_length(
+    role: SequenceRole,
+    sequence_name: &str,
+    hoco_sequence_length: usize,
+    hodeco_map: &impl CoordinateMap,
+) -> Result<(), HodecoError> {
+    if hoco_sequence_length == hodeco_map.total_compressed() {
+        Ok(())
+    } else {
+        Err(HodecoError::SequenceLengthMismatch {
+            role,
+            sequence_name: sequence_name.to_string(),
+            paf_length: hoco_sequence_length,
+            map_length: hodeco_map.total_compressed(),
+        })
+    }
+}
+
+/// Checks that the alignment of a compressed PAF line can be decompressed with the given hodeco maps.
+///
+/// The sequence lengths must match the maps, the compressed query and target ranges must lie within the maps,
+/// the decompressed ranges must lie within the decompressed sequences,
+/// and neither the compressed nor the decompressed ranges may be empty.
+fn check_alignment<Map: CoordinateMap>(
+    hoco_paf: &PAFLine,
+    query_hodeco_map: &Map,
+    target_hodeco_map: &Map,
+) -> Result<(), HodecoError> {
+    for (role, sequence_name, sequence_length, hodeco_map, start, end) in [
+        (
+            SequenceRole::Query,
+            &hoco_paf.query_sequence_name,
+            hoco_paf.query_sequence_length,
+            query_hodeco_map,
+            hoco_paf.query_start_coordinate,
+            hoco_paf.query_end_coordinate,
+        ),
+        (
+            SequenceRole::Target,
+            &hoco_paf.target_sequence_name,
+            hoco_paf.target_sequence_length,
+            target_hodeco_map,
+            hoco_paf.target_start_coordinate_on_original_strand,
+            hoco_paf.target_end_coordinate_on_original_strand,
+        ),
+    ] {
+        check_sequence_length(role, sequence_name, sequence_length, hodeco_map)?;
+        check_range(role, sequence_name, hodeco_map, start, end)?;
+        let (hodeco_start, hodeco_end) = (hodeco_map.expand(start), hodeco_map.expand(end));
+        for coordinate in [hodeco_start, hodeco_end] {
+            check_coordinate(role, sequence_name, coordinate, hodeco_map.total_expanded())?;
+        }
+        if end <= start || hodeco_end <= hodeco_start {
+            return Err(HodecoError::EmptyAlignment {
+                role,
+                sequence_name: sequence_name.clone(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Computes the approximate and the gap-compressed per-base divergence of a difference string.
+///
+/// The approximate divergence is the fraction of mismatches and gap characters among all alignment columns.
+/// The gap-compressed divergence counts each insertion or deletion as a single event instead.
+fn difference_string_divergences(difference_string: &AlignmentDifference) -> (f64, f64) {
+    let mut matches = 0;
+    let mut mismatches = 0;
+    let mut gap_characters = 0;
+    let mut gaps = 0;
+    for difference_column in &difference_string.0 {
+        match difference_column {
+            DifferenceColumn::Match { length } => matches += length,
+            DifferenceColumn::Mismatch { .. } => mismatches += 1,
+            DifferenceColumn::Insertion {
+                superfluous_query_characters: characters,
+            }
+            | DifferenceColumn::Deletion {
+                missing_query_characters: characters,
+            } => {
+                gap_characters += characters.len();
+                gaps += 1;
+            }
+        }
+    }
+
+    let divergence = |differences: usize| {
+        let columns = matches + differences;
+        if columns == 0 {
+            0.0
+        } else {
+            differences as f64 / columns as f64
+        }
+    };
+    (
+        divergence(mismatches + gap_characters),
+        divergence(mismatches + gaps),
+    )
+}
+
+fn check_coordinate(
+    role: SequenceRole,
+    sequence_name: &str,
+    hodeco_coordinate: usize,
+    hodeco_sequence_length: usize,
+) -> Result<(), HodecoError> {
+    if hodeco_coordinate <= hodeco_sequence_length {
+        Ok(())
+    } else {
+        Err(HodecoError::CoordinateOutOfBounds {
+            role,
+            sequence_name: sequence_name.to_string(),
+            coordinate: hodeco_coordinate,
+            decompressed_length: hodeco_sequence_length,
+        })
+    }
+}
+
+/// Decompresses a CIGAR column that starts at the given compressed offsets, and advances the offsets past it.
+fn hodeco_cigar_column(
+    cigar_column: &CigarColumn,
+    query_offset: &mut usize,
+    target_offset: &mut usize,
+    query_hodeco_map: &impl CoordinateMap,
+    target_hodeco_map: &impl CoordinateMap,
+    query_sequence_name: &str,
+    target_sequence_name: &str,
+) -> Result<CigarColumn, HodecoError> {
+    Ok(match cigar_column {
+        CigarColumn::Match(count) => {
+            let query_limit = *query_offset + *count;
+            let target_limit = *target_offset + *count;
+            check_range(
+                SequenceRole::Target,
+                target_sequence_name,
+                target_hodeco_map,
+                *target_offset,
+                target_limit,
+            )?;
+            let hodeco_count = expand_range(
+                SequenceRole::Query,
+                query_sequence_name,
+                query_hodeco_map,
+                *query_offset,
+                query_limit,
+            )?;
+            *query_offset = query_limit;
+            *target_offset = target_limit;
+            CigarColumn::Match(hodeco_count)
+        }
+        CigarColumn::Deletion(count) => {
+            let target_limit = *target_offset + *count;
+            let hodeco_count = expand_range(
+                SequenceRole::Target,
+                target_sequence_name,
+                target_hodeco_map,
+                *target_offset,
+                target_limit,
+            )?;
+            *target_offset = target_limit;
+            CigarColumn::Deletion(hodeco_count)
+        }
+        CigarColumn::Insertion(count) => {
+            let query_limit = *query_offset + *count;
+            let hodeco_count = expand_range(
+                SequenceRole::Query,
+                query_sequence_name,
+                query_hodeco_map,
+                *query_offset,
+                query_limit,
+            )?;
+            *query_offset = query_limit;
+            CigarColumn::Insertion(hodeco_count)
+        }
+        CigarColumn::Mismatch(_) => {
+            return Err(HodecoError::MismatchInCigar {
+                query_sequence_name: query_sequence_name.to_string(),
+            })
+        }
+    })
+}
+
+fn count_cigar_column(
+    hodeco_cigar_column: &CigarColumn,
+    number_of_matching_bases: &mut usize,
+    number_of_bases_and_gaps: &mut usize,
+) {
+    if let CigarColumn::Match(count) = hodeco_cigar_column {
+        *number_of_matching_bases += *count;
+    }
+
+    match hodeco_cigar_column {
+        CigarColumn::Match(count)
+        | CigarColumn::Deletion(count)
+        | CigarColumn::Insertion(count)
+        | CigarColumn::Mismatch(count) => *number_of_bases_and_gaps += *count,
+    }
+}
+
+/// Decompresses a difference column that starts at the given compressed offsets, and advances the offsets past it.
+///
+/// Returns the decompressed column, and for mismatches, the number of additional copies of the mismatch
+/// that are needed to cover the decompressed homopolymer run.
+fn hodeco_difference_column(
+    difference_column: &DifferenceColumn,
+    query_offset: &mut usize,
+    target_offset: &mut usize,
+    query_hodeco_map: &impl CoordinateMap,
+    target_hodeco_map: &impl CoordinateMap,
+    query_sequence_name: &str,
+    target_sequence_name: &str,
+) -> Result<(DifferenceColumn, usize), HodecoError> {
+    check_difference_characters(difference_column, query_sequence_name)?;
+    Ok(match difference_column {
+        DifferenceColumn::Match { length } => {
+            let query_limit = *query_offset + *length;
+            let target_limit = *target_offset + *length;
+            check_range(
+                SequenceRole::Target,
+                target_sequence_name,
+                target_hodeco_map,
+                *target_offset,
+                target_limit,
+            )?;
+            let hodeco_count = expand_range(
+                SequenceRole::Query,
+                query_sequence_name,
+                query_hodeco_map,
+                *query_offset,
+                query_limit,
+            )?;
+            *query_offset = query_limit;
+            *target_offset = target_limit;
+            (
+                DifferenceColumn::Match {
+                    length: hodeco_count,
+                },
+                0,
+            )
+        }
+        DifferenceColumn::Deletion {
+            missing_query_characters,
+        } => {
+            // An empty deletion means that the difference string is malformed.
+            if missing_query_characters.is_empty() {
+                return Err(HodecoError::EmptyIndel {
+                    query_sequence_name: query_sequence_name.to_string(),
+                });
+            }
+            let target_limit = *target_offset + missing_query_characters.len();
+            check_range(
+                SequenceRole::Target,
+                target_sequence_name,
+                target_hodeco_map,
+                *target_offset,
+                target_limit,
+            )?;
+            let missing_query_characters = homopolymer_decompress_string(
+                missing_query_characters,
+                target_hodeco_map,
+                *target_offset,
+            );
+            *target_offset = target_limit;
+            (
+                DifferenceColumn::Deletion {
+                    missing_query_characters,
+                },
+                0,
+            )
+        }
+        DifferenceColumn::Insertion {
+            superfluous_query_characters,
+        } => {
+            // An empty insertion means that the difference string is malformed.
+            if superfluous_query_characters.is_empty() {
+                return Err(HodecoError::EmptyIndel {
+                    query_sequence_name: query_sequence_name.to_string(),
+                });
+            }
+            let query_limit = *query_offset + superfluous_query_characters.len();
+            check_range(
+                SequenceRole::Query,
+                query_sequence_name,
+                query_hodeco_map,
+                *query_offset,
+                query_limit,
+            )?;
+            let superfluous_query_characters = homopolymer_decompress_string(
+                superfluous_query_characters,
+                query_hodeco_map,
+                *query_offset,
+            );
+            *query_offset = query_limit;
+            (
+                DifferenceColumn::Insertion {
+                    superfluous_query_characters,
+                },
+                0,
+            )
+        }
+        DifferenceColumn::Mismatch { reference, query } => {
+            let query_limit = *query_offset + 1;
+            let target_limit = *target_offset + 1;
+            check_range(
+                SequenceRole::Target,
+                target_sequence_name,
+                target_hodeco_map,
+                *target_offset,
+                target_limit,
+            )?;
+            let hodeco_count = expand_range(
+                SequenceRole::Query,
+                query_sequence_name,
+                query_hodeco_map,
+                *query_offset,
+                query_limit,
+            )?
+            .checked_sub(1)
+            .ok_or_else(|| HodecoError::EmptyRun {
+                role: SequenceRole::Query,
+                sequence_name: query_sequence_name.to_string(),
+                position: *query_offset,
+            })?;
+            *query_offset = query_limit;
+            *target_offset = target_limit;
+            (
+                DifferenceColumn::Mismatch {
+                    reference: *reference,
+                    query: *query,
+                },
+                hodeco_count,
+            )
+        }
+    })
+}
+
+/// Checks that all characters of a difference column are ASCII nucleotide codes,
+/// i.e. IUPAC codes in upper or lower case.
+fn check_difference_characters(
+    difference_column: &DifferenceColumn,
+    query_sequence_name: &str,
+) -> Result<(), HodecoError> {
+    let invalid_character = match difference_column {
+        DifferenceColumn::Match { .. } => None,
+        DifferenceColumn::Mismatch { reference, query } => [*reference, *query]
+            .into_iter()
+            .find(|character| !is_nucleotide_code(*character)),
+        DifferenceColumn::Deletion {
+            missing_query_characters: characters,
+        }
+        | DifferenceColumn::Insertion {
+            superfluous_query_characters: characters,
+        } => characters
+            .chars()
+            .find(|character| !is_nucleotide_code(*character)),
+    };
+
+    if let Some(character) = invalid_character {
+        Err(HodecoError::InvalidDifferenceCharacter {
+            query_sequence_name: query_sequence_name.to_string(),
+            character,
+        })
+    } else {
+        Ok(())
+    }
+}
+
+fn is_nucleotide_code(character: char) -> bool {
+    matches!(
+        character.to_ascii_uppercase(),
+        'A' | 'C'
+            | 'G'
+            | 'T'
+            | 'U'
+            | 'R'
+            | 'Y'
+            | 'S'
+            | 'W'
+            | 'K'
+            | 'M'
+            | 'B'
+            | 'D'
+            | 'H'
+            | 'V'
+            | 'N'
+    )
+}
+
+/// Adds the mismatches and gaps of a decompressed difference column to the total.
+///
+/// A mismatch that was expanded into copies for its decompressed homopolymer run
+/// only counts with its additional copies, like minimap2 counts the mismatches of the compressed alignment once.
+pub(crate) fn count_difference_column(
+    hodeco_difference_column: &DifferenceColumn,
+    additional_mismatches: usize,
+    total_number_of_mismatches_and_gaps: &mut usize,
+) {
+    match hodeco_difference_column {
+        DifferenceColumn::Match { .. } => {}
+        DifferenceColumn::Deletion {
+            missing_query_characters,
+        } => *total_number_of_mismatches_and_gaps += missing_query_characters.len(),
+        DifferenceColumn::Insertion {
+            superfluous_query_characters,
+        } => *total_number_of_mismatches_and_gaps += superfluous_query_characters.len(),
+        DifferenceColumn::Mismatch { .. } => {
+            *total_number_of_mismatches_and_gaps += additional_mismatches
+        }
+    }
+}
+
+/// Returns the decompressed length of the compressed range `start..end` of a sequence,
+/// or an error if the range reaches beyond the end of the hodeco map.
+fn expand_range(
+    role: SequenceRole,
+    sequence_name: &str,
+    hodeco_map: &impl CoordinateMap,
+    start: usize,
+    end: usize,
+) -> Result<usize, HodecoError> {
+    check_range(role, sequence_name, hodeco_map, start, end)?;
+    Ok(hodeco_map.expand(end) - hodeco_map.expand(start))
+}
+
+/// Checks that the compressed range `start..end` of a sequence lies within its hodeco map.
+fn check_range(
+    role: SequenceRole,
+    sequence_name: &str,
+    hodeco_map: &impl CoordinateMap,
+    start: usize,
+    end: usize,
+) -> Result<(), HodecoError> {
+    let compressed_length = hodeco_map.total_compressed();
+    if start > compressed_length || end > compressed_length {
+        Err(HodecoError::CoordinateOutOfRange {
+            role,
+            sequence_name: sequence_name.to_string(),
+            start,
+            end,
+            compressed_length,
+        })
+    } else {
+        Ok(())
+    }
+}
+
+fn homopolymer_decompress_string(
+    input: &str,
+    hodeco_map: &impl CoordinateMap,
+    offset: usize,
+) -> String {
+    let mut result = String::new();
+    for (index, character) in input.chars().enumerate() {
+        let count = hodeco_map.expand(offset + index + 1) - hodeco_map.expand(offset + index);
+        for _ in 0..count {
+            result.push(character);
+        }
+    }
+    result
+}
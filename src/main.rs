@@ -1,157 +1,1862 @@
-use cbor::Decoder;
-use clap::Parser;
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use crossbeam::channel;
-use log::{info, LevelFilter};
-use minimap2_paf_io::data::{CigarColumn, DifferenceColumn, PAFLine};
+use indicatif::MultiProgress;
+use log::{debug, error, info, warn, LevelFilter};
+use map_cache::MapCache;
+use map_format::{read_map_entries, MapFormat, MapReadError, MapWriter};
+use minimap2_homopolymer_decompression::{
+    check_difference_string_consistency, check_difference_string_roundtrip,
+    expanded_difference_string_columns, hodeco_coordinate_tag, hodeco_paf_line, split_on_gaps,
+    CoordinateMap, HodecoError, HodecoMap, MapOffsetWidth, MapRepresentation, PafRecord,
+    SequenceRole,
+};
+use minimap2_paf_io::data::PAFLine;
 use minimap2_paf_io::input::parse_line;
+use noodles_bgzf as bgzf;
+use progress::{terminal_progress_bars, Progress, ProgressBarLogger, ProgressReader};
 use simplelog::{ColorChoice, TermLogger, TerminalMode};
-use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ffi::OsStr;
+use std::fmt::Display;
 use std::fs::File;
-use std::io::{BufRead, BufReader, BufWriter, Write};
-use std::path::PathBuf;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+mod map_cache;
+mod map_format;
+mod progress;
 
 #[derive(Parser, Clone, Debug)]
+#[clap(
+    after_help = "EXIT CODES:
+    0    All lines were decompressed successfully.
+    2    The command line arguments are invalid.
+    3    Some lines could not be decompressed and were skipped (see --on-error).
+    4    A line or a hodeco map failed validation.
+    5    A file could not be opened, read or written.
+    101  Internal error.",
+    subcommand_negates_reqs = true,
+    args_conflicts_with_subcommands = true
+)]
 struct Configuration {
+    /// Run a subcommand instead of decompressing alignments.
+    #[clap(subcommand)]
+    command: Option<Command>,
+
     /// The input file. Must be in wtdbg2's .ctg.lay format.
-    #[clap(long, parse(from_os_str))]
-    input: PathBuf,
+    #[clap(
+        long,
+        parse(from_os_str),
+        required_unless_present = "input-list",
+        conflicts_with = "input-list"
+    )]
+    input: Option<PathBuf>,
 
     /// The output file. Must be in wtdbg2's .ctg.lay format.
+    #[clap(
+        long,
+        parse(from_os_str),
+        required_unless_present_any = &["validate-only", "count-only", "input-list"],
+        conflicts_with = "input-list"
+    )]
+    output: Option<PathBuf>,
+
+    /// A file listing multiple input files to decompress with the same hodeco maps, which are loaded only once.
+    /// Each line contains an input file and its output file, separated by a tab.
+    /// With `--validate-only` or `--count-only`, the lines contain only the input file.
     #[clap(long, parse(from_os_str))]
-    output: PathBuf,
+    input_list: Option<PathBuf>,
+
+    /// The number of files from `--input-list` that are decompressed simultaneously.
+    /// Each file gets its own input and output thread and its own `--compute-threads` compute threads.
+    /// If not given, the files are decompressed one after the other.
+    #[clap(long, conflicts_with = "input")]
+    parallel_files: Option<usize>,
+
+    /// Write the output in BGZF format, which can be indexed by tabix.
+    /// This is also enabled if the output file name ends with `.bgz`.
+    /// The blocks are compressed with the default compression level of `bgzip`.
+    #[clap(long)]
+    bgzf: bool,
+
+    /// Flush the output after each alignment, or after each group of alignments with `--group-by-query`,
+    /// such that decompressed lines become visible immediately, e.g. when piping the output into `head`.
+    /// This costs throughput, especially for many short lines, so it should only be used for inspecting the output interactively.
+    /// With `--bgzf`, each flush ends a BGZF block.
+    #[clap(long)]
+    line_buffered: bool,
+
+    /// The format of the output file.
+    #[clap(
+        long,
+        value_enum,
+        default_value = "text",
+        conflicts_with_all = &["emit-original", "passthrough-comments"]
+    )]
+    output_format: OutputFormat,
+
+    /// Decompress all lines without writing any output, only to check that they are valid.
+    /// Use the exit code to find out if the input is valid.
+    #[clap(long, conflicts_with = "output")]
+    validate_only: bool,
+
+    /// Decompress all lines without serialising or writing them, only to log the decompression statistics.
+    /// The statistics are the same as the ones logged at the end of a normal run.
+    #[clap(long, conflicts_with_all = &["output", "validate-only"])]
+    count_only: bool,
+
+    /// What to do if a line cannot be decompressed, e.g. because it is malformed or its hodeco map is missing.
+    #[clap(long, value_enum, default_value = "abort")]
+    on_error: ErrorPolicy,
 
     /// The file containing the homopolymer compression map of the query sequences.
-    #[clap(long, parse(from_os_str))]
-    query_hodeco_map: PathBuf,
+    /// Required unless a subcommand is given.
+    #[clap(long, parse(from_os_str), required = true)]
+    query_hodeco_map: Option<PathBuf>,
 
     /// The file containing the homopolymer compression map of the target sequences.
+    /// Required unless a subcommand is given.
+    #[clap(long, parse(from_os_str), required = true)]
+    target_hodeco_map: Option<PathBuf>,
+
+    /// Apply the maps of `--query-hodeco-map` to the target sequences of the alignments,
+    /// and the maps of `--target-hodeco-map` to the query sequences.
+    /// Use this if the query and target roles of the PAF file are reversed compared to the ones used when creating the maps,
+    /// e.g. if the reads were used as reference.
+    #[clap(long)]
+    swap_roles: bool,
+
+    /// The format of the hodeco map files.
+    #[clap(long, value_enum, default_value = "cbor")]
+    hodeco_map_format: MapFormat,
+
+    /// Cache the hodeco maps in this directory in a format that is much faster to load.
+    /// On later runs, the cache of a map file is used instead of the file itself if the file was not modified,
+    /// judged by its path, size and modification time.
+    /// The cache is not written if `--max-map-entries` is given.
     #[clap(long, parse(from_os_str))]
-    target_hodeco_map: PathBuf,
+    map_cache_dir: Option<PathBuf>,
 
     /// The size of the queues between threads.
     #[clap(long, default_value = "32768")]
     queue_size: usize,
 
-    /// The size of the I/O buffers in bytes.
-    #[clap(long, default_value = "67108864")]
-    io_buffer_size: usize,
+    /// The size of the I/O buffers in bytes.
+    #[clap(long, default_value = "67108864")]
+    io_buffer_size: usize,
+
+    /// The number of compute threads to use for decompression.
+    /// Note that the input and output threads are not counted under this number.
+    /// If zero, then no threads are spawned and all work is done on the main thread.
+    #[clap(long, default_value = "1")]
+    compute_threads: usize,
+
+    /// Only output alignments whose target overlaps one of the given regions.
+    /// Regions are given as `name:start-end`, with zero-based half-open coordinates.
+    /// Can be given multiple times. If not given, all alignments are output.
+    #[clap(long)]
+    target_region: Vec<TargetRegion>,
+
+    /// The coordinate space in which the target regions are given.
+    /// Regions in compressed space are checked before decompressing an alignment,
+    /// regions in decompressed space are checked after decompressing it.
+    #[clap(long, value_enum, default_value = "decompressed")]
+    target_region_space: CoordinateSpace,
+
+    /// Only output alignments whose query is listed in this file.
+    /// The file contains one sequence name per line, further tab-separated columns are ignored.
+    /// Other alignments are dropped before decompressing them.
+    #[clap(long, parse(from_os_str))]
+    query_allowlist: Option<PathBuf>,
+
+    /// Only output alignments whose target is listed in this file.
+    /// The file has the same format as for `--query-allowlist`.
+    #[clap(long, parse(from_os_str))]
+    target_allowlist: Option<PathBuf>,
+
+    /// Add a constant offset per target sequence to the decompressed target coordinates,
+    /// e.g. to place the alignments into the coordinate system of a concatenated reference.
+    /// The file contains one line per target sequence with the sequence name and the non-negative offset, separated by a tab.
+    /// An optional third column gives a new name for the target sequence, e.g. the name of the concatenated reference,
+    /// and may be empty to keep the name.
+    /// An optional fourth column gives the length of the target sequence after offsetting, e.g. the length of the concatenated reference.
+    /// Alignments whose offset target end coordinate exceeds this length are errors.
+    /// Without the fourth column, the target sequence length is increased by the offset.
+    /// Alignments whose target has no entry are output unchanged with a warning.
+    #[clap(long, parse(from_os_str))]
+    target_offset_table: Option<PathBuf>,
+
+    /// What to do if a hodeco map file contains multiple entries for the same sequence.
+    #[clap(long, value_enum, default_value = "last")]
+    on_duplicate_map: DuplicateMapPolicy,
+
+    /// Write the decompressed length of each sequence in the hodeco maps to this file.
+    /// The output is a TSV file with columns `sequence_name` and `decompressed_length`,
+    /// sorted by sequence name.
+    /// It is written right after the hodeco maps are loaded, before any alignments are processed.
+    #[clap(long, parse(from_os_str))]
+    emit_lengths: Option<PathBuf>,
+
+    /// The hodeco maps whose sequence lengths are written to the file given by `--emit-lengths`.
+    #[clap(long, value_enum, default_value = "target")]
+    emit_lengths_of: LengthsSource,
+
+    /// Write the number of alignments referencing each sequence of the hodeco maps to this file.
+    /// The output is a TSV file with columns `sequence_name`, `role` and `times_referenced`,
+    /// sorted by role and sequence name, and includes sequences that were never referenced.
+    /// All alignments of the input are counted, including the ones that are filtered out or skipped.
+    #[clap(long, parse(from_os_str))]
+    map_coverage: Option<PathBuf>,
+
+    /// The representation of the strand field in the output.
+    #[clap(long, value_enum, default_value = "plus-minus")]
+    strand_format: StrandFormat,
+
+    /// The integer width used to store the offsets of the hodeco maps in memory.
+    /// 32 bit offsets halve the memory usage, but only work for sequences with a decompressed length below 4Gbp.
+    /// By default, 32 bits are used for each sequence whose offsets fit.
+    #[clap(long, value_enum, default_value = "auto")]
+    map_offset_width: MapOffsetWidthArgument,
+
+    /// The representation of the hodeco maps in memory.
+    /// Run-length encoded maps only store the runs of characters that were not compressed,
+    /// which saves a lot of memory for sequences that are mostly incompressible,
+    /// at the cost of a binary search for each lookup.
+    #[clap(long, value_enum, default_value = "dense")]
+    map_representation: MapRepresentationArgument,
+
+    /// Choose the representation of each hodeco map automatically, such that all maps together use at most this many bytes.
+    /// The maps are loaded in their smallest representation, and then as many maps as fit into the budget
+    /// are stored densely with the width given by `--map-offset-width`, starting with the ones that need the least additional memory.
+    /// If the maps do not fit even in their smallest representation, a warning is logged and they are kept in it.
+    #[clap(long, conflicts_with = "map-representation")]
+    max_memory: Option<usize>,
+
+    /// Abort if the input PAF file is not sorted by query name and then by target name.
+    /// Names are compared bytewise, like `LC_ALL=C sort -k1,1 -k6,6`.
+    /// The error reports the first out-of-order line and the line it should not come after.
+    #[clap(long)]
+    assert_sorted: bool,
+
+    /// Output the alignments of each query contiguously, sorted by their decompressed query start.
+    /// The input must be sorted by query name,
+    /// since the alignments of a query are collected until the input moves on to a different query.
+    /// Names are compared bytewise, like with `--assert-sorted`.
+    /// If the input is not sorted by query name, a warning is logged,
+    /// and the alignments of a query that are not contiguous are output in multiple groups.
+    #[clap(long, conflicts_with = "emit-original")]
+    group_by_query: bool,
+
+    /// The base of the coordinates in the output.
+    /// This affects the query start and end and the target start and end columns,
+    /// which minimap2 outputs as zero-based half-open intervals.
+    /// With base 1, the start columns are increased by one.
+    /// The end columns are adjusted according to `--coordinate-output-interval`.
+    /// Sequence lengths and the filter options are not affected.
+    #[clap(long, value_enum, default_value = "0")]
+    coordinate_output_base: CoordinateBase,
+
+    /// The interval convention of the coordinates in the output.
+    /// With half-open intervals, the end column is the first position after the alignment,
+    /// with closed intervals it is the last position of the alignment.
+    /// Together with `--coordinate-output-base 1` and closed intervals, the coordinates are as in SAM or GFF.
+    #[clap(long, value_enum, default_value = "half-open")]
+    coordinate_output_interval: IntervalConvention,
+
+    /// Check that the query characters of each decompressed difference string (`cs` tag)
+    /// add up to the decompressed query coordinate range.
+    /// Lines that fail the check are handled according to `--on-error`.
+    #[clap(long)]
+    check_cs_consistency: bool,
+
+    /// Check that homopolymer compressing each decompressed difference string (`cs` tag) with the hodeco maps
+    /// reproduces the original difference string.
+    /// This verifies the duplication of mismatches and the expansion of insertions and deletions.
+    /// Lines that fail the check are handled according to `--on-error`.
+    #[clap(long)]
+    check_cs_roundtrip: bool,
+
+    /// Fail on alignments that have neither a CIGAR string (`cg` tag) nor a difference string (`cs` tag).
+    /// The number of matching bases and the number of bases and gaps of such alignments cannot be recomputed,
+    /// so by default they are output unchanged, i.e. in compressed space, and a warning is logged.
+    /// Lines that fail the check are handled according to `--on-error`.
+    #[clap(long)]
+    require_alignment_string: bool,
+
+    /// Do not decompress lines whose decompressed difference string (`cs` tag) would have more than this many
+    /// individually stored columns, i.e. mismatches and inserted or deleted characters.
+    /// This protects against a single alignment over huge homopolymer runs using up all memory.
+    /// Such lines are handled according to `--on-error`.
+    #[clap(long)]
+    max_expanded_columns: Option<usize>,
+
+    /// Clamp the rescaled divergence values (`dv` and `de` tags) into [0, 1].
+    /// A warning is emitted for each value outside of [0, 1] regardless of this flag.
+    #[clap(long)]
+    clamp_divergence: bool,
+
+    /// Only output the optional tags with the given names, e.g. `NM,cg`.
+    /// The twelve mandatory columns are always output.
+    #[clap(long, use_value_delimiter = true, conflicts_with = "drop-tags")]
+    keep_tags: Vec<String>,
+
+    /// Do not output the optional tags with the given names, e.g. `cs,MD`.
+    #[clap(long, use_value_delimiter = true)]
+    drop_tags: Vec<String>,
+
+    /// Split alignments at insertions and deletions that are longer than this after decompression.
+    /// Such gaps are likely caused by a single compressed character that maps to a huge decompressed run,
+    /// e.g. an assembly gap encoded as a run of `N`s.
+    /// The gap is removed and the parts before and after it are output as separate records,
+    /// with their own coordinates, CIGAR string, difference string and base counts.
+    /// All other fields are copied from the original alignment.
+    #[clap(long)]
+    split_on_gap: Option<usize>,
+
+    /// Append tags describing the homopolymer decompression to each output record, after all other optional fields:
+    /// `hd:i:1` marks the record as decompressed by this tool,
+    /// and `qx:i:` and `tx:i:` give the number of characters by which the query and target ranges of the alignment grew.
+    /// For alignments split by `--split-on-gap`, the growth refers to the whole alignment before splitting.
+    /// The tags are not affected by `--keep-tags` and `--drop-tags`.
+    #[clap(long)]
+    annotate_decompression: bool,
+
+    /// Homopolymer decompress the coordinates stored in a custom integer tag, given as `tag:role`, e.g. `zs:query`.
+    /// The role is `query` or `target` and selects the hodeco map the coordinates are remapped through.
+    /// Can be given multiple times.
+    /// Lines where the tag is missing or does not hold a valid compressed coordinate are handled according to `--on-error`.
+    #[clap(long)]
+    remap_tag: Vec<RemapTag>,
+
+    /// Stop loading each hodeco map file after this many entries.
+    /// This is a testing aid for running a small subset of alignments against a huge map file,
+    /// and must not be used in production.
+    /// Alignments of sequences whose hodeco map was not loaded are handled according to `--on-error`.
+    #[clap(long)]
+    max_map_entries: Option<usize>,
+
+    /// Output the original compressed line of each alignment together with its decompressed records.
+    /// Without `--original-output`, the original line is written into the output before the decompressed records,
+    /// prefixed with `C<TAB>`, while the decompressed records are prefixed with `D<TAB>`.
+    #[clap(long)]
+    emit_original: bool,
+
+    /// Write the original lines of `--emit-original` into this file instead of the output.
+    /// Its lines correspond one-to-one to the lines of the output,
+    /// i.e. the original line is repeated for each record of a split alignment, and comments are copied into both files.
+    #[clap(
+        long,
+        parse(from_os_str),
+        requires = "emit-original",
+        conflicts_with = "input-list"
+    )]
+    original_output: Option<PathBuf>,
+
+    /// Copy comment lines of the input, i.e. lines starting with `#`, verbatim to the output.
+    /// By default, they are dropped.
+    /// With multiple compute threads, comments may be reordered relative to the alignments,
+    /// like the alignments among themselves.
+    #[clap(long)]
+    passthrough_comments: bool,
+
+    /// Log the progress of loading the query and target hodeco maps and of processing the alignments
+    /// every this many seconds.
+    /// Map loading progress is measured in bytes read from the map files, and processing progress in input lines.
+    /// If stderr is a terminal, a progress bar is displayed for each of these phases instead.
+    /// If not given, no progress is logged.
+    #[clap(long)]
+    progress_interval: Option<u64>,
+
+    /// Write all warnings about individual alignments to this file, in addition to logging them.
+    /// The output is a TSV file with columns `input_file`, `line_number`, `sequence_name`, `warning_type` and `message`,
+    /// sorted by input file and line number.
+    /// Warnings about skipped lines have the kind of the error as type, e.g. `missing-map` or `coordinate-out-of-bounds`.
+    /// The file is written at the end of the run, so it is not written if the run is aborted.
+    #[clap(long, parse(from_os_str))]
+    warnings_file: Option<PathBuf>,
+
+    /// Print the full context of the first line that cannot be parsed or decompressed to stderr:
+    /// the raw input line, the parsed line, the hodeco map entries around its start and end coordinates and the error.
+    /// The line is then handled according to `--on-error` as usual.
+    /// This keeps a copy of each input line until it is processed, which costs some throughput.
+    #[clap(long)]
+    dump_first_failure: bool,
+
+    /// The level of log messages to be produced.
+    #[clap(long, default_value = "Info")]
+    log_level: LevelFilter,
+}
+
+#[derive(Subcommand, Clone, Debug)]
+enum Command {
+    /// Convert a hodeco map file into a different format.
+    ConvertMap(ConvertMapConfiguration),
+}
+
+#[derive(Args, Clone, Debug)]
+struct ConvertMapConfiguration {
+    /// The format of the input file.
+    #[clap(long, value_enum)]
+    from: MapFormat,
+
+    /// The format of the output file.
+    #[clap(long, value_enum)]
+    to: MapFormat,
+
+    /// The input hodeco map file.
+    #[clap(parse(from_os_str))]
+    input: PathBuf,
+
+    /// The output hodeco map file.
+    #[clap(parse(from_os_str))]
+    output: PathBuf,
+
+    /// The level of log messages to be produced.
+    #[clap(long, default_value = "Info")]
+    log_level: LevelFilter,
+}
+
+/// The exit codes of this tool.
+/// Invalid command line arguments are reported by clap with exit code 2,
+/// and internal errors cause a panic with exit code 101.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum ExitCode {
+    /// The command line arguments are invalid.
+    InvalidArguments = 2,
+    /// Some lines could not be decompressed and were skipped.
+    LinesSkipped = 3,
+    /// A line or a hodeco map failed validation.
+    ValidationFailed = 4,
+    /// A file could not be opened, read or written.
+    IoError = 5,
+}
+
+/// Logs the given message as error and exits with the given exit code.
+fn exit_with(exit_code: ExitCode, message: impl Display) -> ! {
+    error!("{message}");
+    process::exit(exit_code as i32)
+}
+
+/// What to do if a line cannot be decompressed.
+#[derive(ValueEnum, Clone, Copy, Debug, Eq, PartialEq)]
+enum ErrorPolicy {
+    /// Exit with an error.
+    Abort,
+    /// Log a warning and skip the line, and exit with an error after processing all other lines.
+    Skip,
+}
+
+/// The coordinate space of a position.
+#[derive(ValueEnum, Clone, Copy, Debug, Eq, PartialEq)]
+enum CoordinateSpace {
+    /// Coordinates in homopolymer compressed space, i.e. as output by minimap2.
+    Compressed,
+    /// Coordinates in homopolymer decompressed space, i.e. as output by this tool.
+    Decompressed,
+}
+
+/// What to do with duplicate entries in a hodeco map file.
+#[derive(ValueEnum, Clone, Copy, Debug, Eq, PartialEq)]
+enum DuplicateMapPolicy {
+    /// Keep the last entry of a sequence.
+    Last,
+    /// Keep the first entry of a sequence.
+    First,
+    /// Abort if a sequence has multiple entries.
+    Error,
+}
+
+/// The hodeco maps to take sequence lengths from.
+#[derive(ValueEnum, Clone, Copy, Debug, Eq, PartialEq)]
+enum LengthsSource {
+    /// Only the query hodeco maps.
+    Query,
+    /// Only the target hodeco maps.
+    Target,
+    /// Both the query and the target hodeco maps.
+    Both,
+}
+
+/// The format of the output file.
+#[derive(ValueEnum, Clone, Copy, Debug, Eq, PartialEq)]
+enum OutputFormat {
+    /// PAF as written by minimap2.
+    Text,
+    /// A sequence of CBOR items, one per alignment record, which is more compact and faster to read than text.
+    /// See the documentation of `PafRecord` in the library for the schema.
+    Cbor,
+}
+
+/// The representation of the strand field in the output.
+#[derive(ValueEnum, Clone, Copy, Debug, Eq, PartialEq)]
+enum StrandFormat {
+    /// `+` for the forward strand and `-` for the reverse strand, as in minimap2's output.
+    PlusMinus,
+    /// `0` for the forward strand and `1` for the reverse strand.
+    ZeroOne,
+    /// `fwd` for the forward strand and `rev` for the reverse strand.
+    FwdRev,
+}
+
+impl StrandFormat {
+    fn format(self, strand: bool) -> &'static str {
+        match (self, strand) {
+            (StrandFormat::PlusMinus, true) => "+",
+            (StrandFormat::PlusMinus, false) => "-",
+            (StrandFormat::ZeroOne, true) => "0",
+            (StrandFormat::ZeroOne, false) => "1",
+            (StrandFormat::FwdRev, true) => "fwd",
+            (StrandFormat::FwdRev, false) => "rev",
+        }
+    }
+}
+
+/// The integer width used to store the offsets of the hodeco maps in memory.
+#[derive(ValueEnum, Clone, Copy, Debug, Eq, PartialEq)]
+enum MapOffsetWidthArgument {
+    /// Use 32 bits if the offsets of a sequence fit, and 64 bits otherwise.
+    Auto,
+    /// Always use 32 bits.
+    #[clap(name = "32")]
+    Bits32,
+    /// Always use 64 bits.
+    #[clap(name = "64")]
+    Bits64,
+}
+
+impl From<MapOffsetWidthArgument> for MapOffsetWidth {
+    fn from(argument: MapOffsetWidthArgument) -> Self {
+        match argument {
+            MapOffsetWidthArgument::Auto => MapOffsetWidth::Auto,
+            MapOffsetWidthArgument::Bits32 => MapOffsetWidth::Bits32,
+            MapOffsetWidthArgument::Bits64 => MapOffsetWidth::Bits64,
+        }
+    }
+}
+
+/// The representation of the hodeco maps in memory.
+#[derive(ValueEnum, Clone, Copy, Debug, Eq, PartialEq)]
+enum MapRepresentationArgument {
+    /// Store all offsets, with the width given by `--map-offset-width`.
+    Dense,
+    /// Store the offsets run-length encoded.
+    RunLength,
+    /// Store the offsets of each sequence run-length encoded if that uses less memory.
+    Smallest,
+}
+
+impl From<MapRepresentationArgument> for MapRepresentation {
+    fn from(argument: MapRepresentationArgument) -> Self {
+        match argument {
+            MapRepresentationArgument::Dense => MapRepresentation::Dense,
+            MapRepresentationArgument::RunLength => MapRepresentation::RunLength,
+            MapRepresentationArgument::Smallest => MapRepresentation::Smallest,
+        }
+    }
+}
+
+/// Checks that PAF lines are sorted by query name and then by target name.
+#[derive(Default)]
+struct SortednessCheck {
+    previous_query_sequence_name: String,
+    previous_target_sequence_name: String,
+    previous_line_number: usize,
+}
+
+impl SortednessCheck {
+    fn check(&mut self, paf_line: &PAFLine, line_number: LineNumber) {
+        let key = (
+            paf_line.query_sequence_name.as_str(),
+            paf_line.target_sequence_name.as_str(),
+        );
+        let previous_key = (
+            self.previous_query_sequence_name.as_str(),
+            self.previous_target_sequence_name.as_str(),
+        );
+
+        if self.previous_line_number > 0 && key < previous_key {
+            exit_with(
+                ExitCode::ValidationFailed,
+                format!(
+                    "Input is not sorted by (query, target): line {line_number} {key:?} comes after line {} {previous_key:?}",
+                    self.previous_line_number
+                ),
+            );
+        }
+
+        if key != previous_key {
+            self.previous_query_sequence_name = key.0.to_string();
+            self.previous_target_sequence_name = key.1.to_string();
+        }
+        self.previous_line_number = line_number.number;
+    }
+}
+
+/// The base of coordinates.
+#[derive(ValueEnum, Clone, Copy, Debug, Eq, PartialEq)]
+enum CoordinateBase {
+    /// The first position of a sequence is 0.
+    #[clap(name = "0")]
+    Zero,
+    /// The first position of a sequence is 1.
+    #[clap(name = "1")]
+    One,
+}
+
+/// The convention for the end of an interval.
+#[derive(ValueEnum, Clone, Copy, Debug, Eq, PartialEq)]
+enum IntervalConvention {
+    /// The end is the first position after the interval.
+    HalfOpen,
+    /// The end is the last position of the interval.
+    Closed,
+}
+
+/// A region of a target sequence, given as `name:start-end` on the command line.
+#[derive(Clone, Debug)]
+struct TargetRegion {
+    sequence_name: String,
+    start: usize,
+    end: usize,
+}
+
+impl FromStr for TargetRegion {
+    type Err = String;
+
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        let (sequence_name, range) = string
+            .rsplit_once(':')
+            .ok_or_else(|| format!("Missing ':' in target region: {string}"))?;
+        let (start, end) = range
+            .split_once('-')
+            .ok_or_else(|| format!("Missing '-' in target region: {string}"))?;
+        let start = start
+            .parse()
+            .map_err(|error| format!("Cannot parse target region start: {error}"))?;
+        let end = end
+            .parse()
+            .map_err(|error| format!("Cannot parse target region end: {error}"))?;
+
+        if start >= end {
+            return Err(format!("Target region is empty: {string}"));
+        }
+
+        Ok(Self {
+            sequence_name: sequence_name.to_string(),
+            start,
+            end,
+        })
+    }
+}
+
+/// A custom integer tag holding coordinates, given as `tag:role` on the command line.
+#[derive(Clone, Debug)]
+struct RemapTag {
+    tag: String,
+    role: SequenceRole,
+}
+
+impl FromStr for RemapTag {
+    type Err = String;
+
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        let (tag, role) = string
+            .split_once(':')
+            .ok_or_else(|| format!("Missing ':' in remap tag: {string}"))?;
+        if tag.len() != 2
+            || !tag
+                .chars()
+                .all(|character| character.is_ascii_alphanumeric())
+        {
+            return Err(format!(
+                "Tag name must be two alphanumeric characters: {tag}"
+            ));
+        }
+        let role = match role {
+            "query" => SequenceRole::Query,
+            "target" => SequenceRole::Target,
+            role => return Err(format!("Role must be query or target: {role}")),
+        };
+
+        Ok(Self {
+            tag: tag.to_string(),
+            role,
+        })
+    }
+}
+
+impl TargetRegion {
+    /// Returns true if the target of the given PAF line overlaps this region.
+    fn overlaps(&self, paf_line: &PAFLine) -> bool {
+        paf_line.target_sequence_name == self.sequence_name
+            && paf_line.target_start_coordinate_on_original_strand < self.end
+            && paf_line.target_end_coordinate_on_original_strand > self.start
+    }
+}
+
+/// Returns true if the given target regions are empty, or if the target of the given PAF line overlaps any of them.
+fn is_in_target_regions(paf_line: &PAFLine, target_regions: &[TargetRegion]) -> bool {
+    target_regions.is_empty()
+        || target_regions
+            .iter()
+            .any(|target_region| target_region.overlaps(paf_line))
+}
+
+/// Initialises logging to stderr.
+/// If progress bars are given, they are hidden while a message is written.
+fn initialise_logging(log_level: &LevelFilter, progress_bars: Option<&MultiProgress>) {
+    let logger = *TermLogger::new(
+        *log_level,
+        Default::default(),
+        TerminalMode::Stderr,
+        ColorChoice::Auto,
+    );
+    log::set_max_level(*log_level);
+    if let Some(progress_bars) = progress_bars {
+        log::set_boxed_logger(Box::new(ProgressBarLogger::new(
+            logger,
+            progress_bars.clone(),
+        )))
+    } else {
+        log::set_boxed_logger(Box::new(logger))
+    }
+    .unwrap();
+    info!("Logging initialised successfully")
+}
+
+fn load_hodeco_maps(
+    path: &Path,
+    role: &str,
+    configuration: &Configuration,
+    progress_bars: Option<&MultiProgress>,
+) -> HashMap<String, HodecoMap> {
+    let on_duplicate_map = configuration.on_duplicate_map;
+    let max_map_entries = configuration.max_map_entries;
+
+    let map_cache = configuration.map_cache_dir.as_ref().map(|map_cache_dir| {
+        MapCache::new(map_cache_dir, path).unwrap_or_else(|error| {
+            exit_with(
+                ExitCode::IoError,
+                format!("Cannot locate cache of {role} hodeco map file: {error:?}"),
+            )
+        })
+    });
+    let cache_is_valid = matches!(&map_cache, Some(map_cache) if map_cache.is_valid());
+    let (hodeco_map_file, hodeco_map_format) = if cache_is_valid {
+        info!("Loading {role} hodeco maps from cache");
+        let map_cache_file = map_cache.as_ref().unwrap().open();
+        (map_cache_file, MapFormat::Packed)
+    } else {
+        (File::open(path), configuration.hodeco_map_format)
+    };
+    let hodeco_map_file = hodeco_map_file.unwrap_or_else(|error| {
+        exit_with(
+            ExitCode::IoError,
+            format!("Cannot open {role} hodeco map file: {error:?}"),
+        )
+    });
+    let mut map_cache_writer = match &map_cache {
+        Some(map_cache) if !cache_is_valid && max_map_entries.is_none() => {
+            Some(map_cache.create().unwrap_or_else(|error| {
+                exit_with(
+                    ExitCode::IoError,
+                    format!("Cannot create cache of {role} hodeco map file: {error:?}"),
+                )
+            }))
+        }
+        _ => None,
+    };
+    let file_size = hodeco_map_file
+        .metadata()
+        .ok()
+        .map(|metadata| metadata.len());
+    let mut progress_reader = ProgressReader::new(
+        hodeco_map_file,
+        Progress::new(
+            format!("loading {role} map"),
+            "bytes",
+            file_size,
+            configuration.progress_interval.map(Duration::from_secs),
+            progress_bars,
+        ),
+    );
+    let hodeco_map_reader =
+        BufReader::with_capacity(configuration.io_buffer_size, &mut progress_reader);
+
+    let mut hodeco_maps = HashMap::new();
+    let mut duplicate_count = 0usize;
+    for item in read_map_entries(hodeco_map_reader, hodeco_map_format)
+        .take(max_map_entries.unwrap_or(usize::MAX))
+    {
+        let (sequence_name, hodeco_map) = item.unwrap_or_else(|error| {
+            exit_with_map_read_error(
+                error,
+                &format!("{role} hodeco map file"),
+                hodeco_maps.len() + duplicate_count,
+            )
+        });
+        if let Some(map_cache_writer) = &mut map_cache_writer {
+            map_cache_writer
+                .write_entry(&sequence_name, &hodeco_map)
+                .unwrap_or_else(|error| {
+                    exit_with(
+                        ExitCode::IoError,
+                        format!("Cannot write cache of {role} hodeco map file: {error:?}"),
+                    )
+                });
+        }
+        let hodeco_map = HodecoMap::from_offsets(hodeco_map, configuration.map_offset_width.into()).unwrap_or_else(|| {
+            exit_with(
+                ExitCode::ValidationFailed,
+                format!("The {role} hodeco map of sequence {sequence_name} does not fit into 32 bit offsets"),
+            )
+        }).into_representation(if configuration.max_memory.is_some() {
+            MapRepresentation::Smallest
+        } else {
+            configuration.map_representation.into()
+        });
+
+        match hodeco_maps.entry(sequence_name) {
+            Entry::Vacant(entry) => {
+                entry.insert(hodeco_map);
+            }
+            Entry::Occupied(mut entry) => {
+                duplicate_count += 1;
+                match on_duplicate_map {
+                    DuplicateMapPolicy::Last => {
+                        entry.insert(hodeco_map);
+                    }
+                    DuplicateMapPolicy::First => {}
+                    DuplicateMapPolicy::Error => exit_with(
+                        ExitCode::ValidationFailed,
+                        format!("Duplicate {role} hodeco map for sequence: {}", entry.key()),
+                    ),
+                }
+            }
+        }
+    }
+
+    progress_reader.finish();
+
+    if let Some(mut map_cache_writer) = map_cache_writer {
+        map_cache_writer
+            .flush()
+            .and_then(|()| map_cache.as_ref().unwrap().commit())
+            .unwrap_or_else(|error| {
+                exit_with(
+                    ExitCode::IoError,
+                    format!("Cannot write cache of {role} hodeco map file: {error:?}"),
+                )
+            });
+        info!("Cached {role} hodeco maps");
+    }
+
+    if let Some(max_map_entries) = max_map_entries {
+        if hodeco_maps.len() + duplicate_count == max_map_entries {
+            warn!("Stopped loading the {role} hodeco map file after {max_map_entries} entries as requested by --max-map-entries");
+        }
+    }
+
+    if duplicate_count > 0 {
+        warn!("Found {duplicate_count} duplicate entries in {role} hodeco map file, kept the {} entry of each", match on_duplicate_map {
+            DuplicateMapPolicy::Last => "last",
+            DuplicateMapPolicy::First => "first",
+            DuplicateMapPolicy::Error => unreachable!(),
+        });
+    }
+    info!(
+        "Loaded {} {role} hodeco maps using {}MiB",
+        hodeco_maps.len(),
+        hodeco_maps
+            .values()
+            .map(HodecoMap::size_in_bytes)
+            .sum::<usize>()
+            / (1024 * 1024)
+    );
+
+    hodeco_maps
+}
+
+/// Exits with the exit code corresponding to the given error that occurred while reading a hodeco map file.
+fn exit_with_map_read_error(error: MapReadError, file_description: &str, entry_count: usize) -> ! {
+    match error {
+        MapReadError::Io(error) => exit_with(
+            ExitCode::IoError,
+            format!("Cannot read {file_description}: {error:?}"),
+        ),
+        MapReadError::Truncated(message) => exit_with(
+            ExitCode::ValidationFailed,
+            format!(
+                "The {file_description} appears truncated: the last entry is incomplete after {entry_count} complete entries ({message})"
+            ),
+        ),
+        MapReadError::Malformed(message) => exit_with(
+            ExitCode::ValidationFailed,
+            format!("Cannot decode {file_description}: {message}"),
+        ),
+    }
+}
+
+/// Converts a hodeco map file into a different format.
+fn convert_map(configuration: &ConvertMapConfiguration) {
+    let input_file = File::open(&configuration.input).unwrap_or_else(|error| {
+        exit_with(
+            ExitCode::IoError,
+            format!("Cannot open input hodeco map file: {error:?}"),
+        )
+    });
+    let output_file = File::create(&configuration.output).unwrap_or_else(|error| {
+        exit_with(
+            ExitCode::IoError,
+            format!("Cannot open output hodeco map file: {error:?}"),
+        )
+    });
+
+    let mut map_writer = MapWriter::new(output_file, configuration.to).unwrap_or_else(|error| {
+        exit_with(
+            ExitCode::IoError,
+            format!("Cannot write output hodeco map file: {error:?}"),
+        )
+    });
+    let mut entry_count = 0;
+    for item in read_map_entries(input_file, configuration.from) {
+        let (sequence_name, offsets) = item.unwrap_or_else(|error| {
+            exit_with_map_read_error(error, "input hodeco map file", entry_count)
+        });
+        map_writer
+            .write_entry(&sequence_name, &offsets)
+            .unwrap_or_else(|error| {
+                exit_with(
+                    ExitCode::IoError,
+                    format!("Cannot write output hodeco map file: {error:?}"),
+                )
+            });
+        entry_count += 1;
+    }
+    map_writer.flush().unwrap_or_else(|error| {
+        exit_with(
+            ExitCode::IoError,
+            format!("Cannot flush output hodeco map file: {error:?}"),
+        )
+    });
+    info!("Converted {entry_count} hodeco map entries");
+}
+
+/// Stores as many of the given hodeco maps densely as fit into the memory budget for `--max-memory`.
+/// The maps must be in their smallest representation, and those that are not stored densely are kept in it.
+fn fit_hodeco_maps_into_memory(
+    mut query_hodeco_maps: HashMap<String, HodecoMap>,
+    mut target_hodeco_maps: HashMap<String, HodecoMap>,
+    max_memory: usize,
+    width: MapOffsetWidth,
+) -> (HashMap<String, HodecoMap>, HashMap<String, HodecoMap>) {
+    let smallest_size: usize = query_hodeco_maps
+        .values()
+        .chain(target_hodeco_maps.values())
+        .map(HodecoMap::size_in_bytes)
+        .sum();
+    if smallest_size > max_memory {
+        warn!("The hodeco maps use {smallest_size} bytes even in their smallest representation, which exceeds the memory budget of {max_memory} bytes, keeping them in their smallest representation");
+        return (query_hodeco_maps, target_hodeco_maps);
+    }
+
+    // The additional memory needed to store each map densely, for the maps that are not dense already.
+    let mut upgrades: Vec<_> = [(true, &query_hodeco_maps), (false, &target_hodeco_maps)]
+        .into_iter()
+        .flat_map(|(is_query, hodeco_maps)| {
+            hodeco_maps
+                .iter()
+                .filter(|(_, hodeco_map)| matches!(hodeco_map, HodecoMap::RunLength(_)))
+                .map(move |(sequence_name, hodeco_map)| {
+                    (
+                        hodeco_map
+                            .dense_size_in_bytes(width)
+                            .saturating_sub(hodeco_map.size_in_bytes()),
+                        is_query,
+                        sequence_name.clone(),
+                    )
+                })
+        })
+        .collect();
+    upgrades.sort_unstable();
+
+    let mut total_size = smallest_size;
+    for (additional_size, is_query, sequence_name) in upgrades {
+        if total_size + additional_size > max_memory {
+            break;
+        }
+        let hodeco_maps = if is_query {
+            &mut query_hodeco_maps
+        } else {
+            &mut target_hodeco_maps
+        };
+        let hodeco_map = hodeco_maps.remove(&sequence_name).unwrap();
+        // The width was validated when loading the map.
+        hodeco_maps.insert(sequence_name, hodeco_map.into_dense(width).unwrap());
+        total_size += additional_size;
+    }
+
+    let mut run_length_count = 0usize;
+    for (role, hodeco_maps) in [
+        ("query", &query_hodeco_maps),
+        ("target", &target_hodeco_maps),
+    ] {
+        for (sequence_name, hodeco_map) in hodeco_maps {
+            let representation = match hodeco_map {
+                HodecoMap::Narrow(_) => "densely with 32 bit offsets",
+                HodecoMap::Wide(_) => "densely with 64 bit offsets",
+                HodecoMap::RunLength(_) => {
+                    run_length_count += 1;
+                    "run-length encoded"
+                }
+            };
+            debug!(
+                "Storing the {role} hodeco map of {sequence_name} {representation} using {} bytes",
+                hodeco_map.size_in_bytes()
+            );
+        }
+    }
+    info!(
+        "Chose the representation of the hodeco maps for a memory budget of {max_memory} bytes: {} dense and {run_length_count} run-length encoded, using an estimated {total_size} bytes",
+        query_hodeco_maps.len() + target_hodeco_maps.len() - run_length_count
+    );
+    (query_hodeco_maps, target_hodeco_maps)
+}
+
+/// Loads the sequence names of an allowlist file.
+/// Warns about names that have no hodeco map, since alignments of such sequences cannot be decompressed.
+fn load_allowlist(
+    path: &Path,
+    role: &str,
+    hodeco_maps: &HashMap<String, HodecoMap>,
+) -> HashSet<String> {
+    let allowlist = std::fs::read_to_string(path).unwrap_or_else(|error| {
+        exit_with(
+            ExitCode::IoError,
+            format!("Cannot read {role} allowlist: {error:?}"),
+        )
+    });
+    let names: HashSet<String> = allowlist
+        .lines()
+        .filter_map(|line| line.split('\t').next())
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+        .collect();
+
+    let missing_names = names
+        .iter()
+        .filter(|name| !hodeco_maps.contains_key(*name))
+        .count();
+    if missing_names > 0 {
+        warn!(
+            "{missing_names} of the {} names in the {role} allowlist have no {role} hodeco map",
+            names.len()
+        );
+    }
+    info!("Loaded {} names from the {role} allowlist", names.len());
+    names
+}
+
+/// The entry of a target sequence in the `--target-offset-table`.
+#[derive(Clone, Debug)]
+struct TargetOffset {
+    offset: usize,
+    /// The new name of the target sequence, if it is renamed.
+    name: Option<String>,
+    /// The length of the target sequence after offsetting, if given.
+    length: Option<usize>,
+}
+
+/// Loads the offsets of the `--target-offset-table`.
+fn load_target_offset_table(path: &Path) -> HashMap<String, TargetOffset> {
+    let table = std::fs::read_to_string(path).unwrap_or_else(|error| {
+        exit_with(
+            ExitCode::IoError,
+            format!("Cannot read target offset table: {error:?}"),
+        )
+    });
+
+    let mut target_offsets = HashMap::new();
+    for (index, line) in table.lines().enumerate() {
+        if line.is_empty() {
+            continue;
+        }
+        let columns: Vec<_> = line.split('\t').collect();
+        let invalid_line = |reason: &str| -> ! {
+            exit_with(
+                ExitCode::ValidationFailed,
+                format!(
+                    "Line {} of the target offset table {reason}: {line}",
+                    index + 1
+                ),
+            )
+        };
+        if columns.len() < 2 || columns.len() > 4 {
+            invalid_line("does not have two to four columns");
+        }
+        let offset = match columns[1].parse::<i64>() {
+            Ok(offset) if offset < 0 => invalid_line("has a negative offset"),
+            Ok(offset) => offset as usize,
+            Err(_) => invalid_line("has an offset that is not an integer"),
+        };
+        let length = columns.get(3).map(|length| {
+            length
+                .parse()
+                .unwrap_or_else(|_| invalid_line("has a length that is not an unsigned integer"))
+        });
+        let target_offset = TargetOffset {
+            offset,
+            name: columns
+                .get(2)
+                .filter(|name| !name.is_empty())
+                .map(|name| name.to_string()),
+            length,
+        };
+        if target_offsets
+            .insert(columns[0].to_string(), target_offset)
+            .is_some()
+        {
+            invalid_line("repeats a sequence name");
+        }
+    }
+    info!("Loaded {} target offsets", target_offsets.len());
+    target_offsets
+}
+
+fn write_lengths(
+    path: &Path,
+    io_buffer_size: usize,
+    query_hodeco_maps: &HashMap<String, HodecoMap>,
+    target_hodeco_maps: &HashMap<String, HodecoMap>,
+    lengths_source: LengthsSource,
+) {
+    let mut lengths = BTreeMap::new();
+    for (role, hodeco_maps) in [("query", query_hodeco_maps), ("target", target_hodeco_maps)] {
+        if (role == "query" && lengths_source == LengthsSource::Target)
+            || (role == "target" && lengths_source == LengthsSource::Query)
+        {
+            continue;
+        }
+
+        for (sequence_name, hodeco_map) in hodeco_maps {
+            let length = hodeco_map.decompressed_length();
+            if let Some(previous_length) = lengths.insert(sequence_name.as_str(), length) {
+                if previous_length != length {
+                    warn!("Sequence {sequence_name} has decompressed length {previous_length} in the query hodeco map, but {length} in the target hodeco map, using the latter");
+                }
+            }
+        }
+    }
+
+    let lengths_file = File::create(path).unwrap_or_else(|error| {
+        exit_with(
+            ExitCode::IoError,
+            format!("Cannot open lengths file: {error:?}"),
+        )
+    });
+    let mut lengths_writer = BufWriter::with_capacity(io_buffer_size, lengths_file);
+    for (sequence_name, length) in &lengths {
+        writeln!(lengths_writer, "{sequence_name}\t{length}").unwrap_or_else(|error| {
+            exit_with(
+                ExitCode::IoError,
+                format!("Cannot write lengths file: {error:?}"),
+            )
+        });
+    }
+    lengths_writer.flush().unwrap_or_else(|error| {
+        exit_with(
+            ExitCode::IoError,
+            format!("Cannot flush lengths file: {error:?}"),
+        )
+    });
+    info!("Wrote {} sequence lengths", lengths.len());
+}
+
+fn main() {
+    let configuration = Configuration::parse();
+    if let Some(Command::ConvertMap(convert_map_configuration)) = &configuration.command {
+        initialise_logging(&convert_map_configuration.log_level, None);
+        convert_map(convert_map_configuration);
+        return;
+    }
+
+    let progress_bars = terminal_progress_bars();
+    initialise_logging(&configuration.log_level, progress_bars.as_ref());
+
+    let inputs = if let Some(input_list) = &configuration.input_list {
+        read_input_list(
+            input_list,
+            configuration.validate_only || configuration.count_only,
+        )
+    } else {
+        vec![Input {
+            input: configuration
+                .input
+                .clone()
+                .expect("--input is required without --input-list"),
+            output: configuration.output.clone(),
+        }]
+    };
+
+    info!("Loading hodeco maps...");
+    // The maps are required if no subcommand is given.
+    let query_hodeco_maps = load_hodeco_maps(
+        configuration.query_hodeco_map.as_ref().unwrap(),
+        "query",
+        &configuration,
+        progress_bars.as_ref(),
+    );
+    let target_hodeco_maps = load_hodeco_maps(
+        configuration.target_hodeco_map.as_ref().unwrap(),
+        "target",
+        &configuration,
+        progress_bars.as_ref(),
+    );
+    let (query_hodeco_maps, target_hodeco_maps) = if let Some(max_memory) = configuration.max_memory
+    {
+        fit_hodeco_maps_into_memory(
+            query_hodeco_maps,
+            target_hodeco_maps,
+            max_memory,
+            configuration.map_offset_width.into(),
+        )
+    } else {
+        (query_hodeco_maps, target_hodeco_maps)
+    };
+    let (query_hodeco_maps, target_hodeco_maps) = if configuration.swap_roles {
+        info!("Swapping the roles of the query and target hodeco maps");
+        (target_hodeco_maps, query_hodeco_maps)
+    } else {
+        (query_hodeco_maps, target_hodeco_maps)
+    };
+
+    let query_allowlist_names = configuration
+        .query_allowlist
+        .as_ref()
+        .map(|path| load_allowlist(path, "query", &query_hodeco_maps));
+    let target_allowlist_names = configuration
+        .target_allowlist
+        .as_ref()
+        .map(|path| load_allowlist(path, "target", &target_hodeco_maps));
+    let target_offsets = configuration
+        .target_offset_table
+        .as_ref()
+        .map(|path| load_target_offset_table(path));
+    let context = RunContext {
+        query_hodeco_maps,
+        target_hodeco_maps,
+        query_allowlist_names,
+        target_allowlist_names,
+        target_offsets,
+        progress_bars,
+    };
+
+    if let Some(emit_lengths) = &configuration.emit_lengths {
+        info!("Writing sequence lengths...");
+        write_lengths(
+            emit_lengths,
+            configuration.io_buffer_size,
+            &context.query_hodeco_maps,
+            &context.target_hodeco_maps,
+            configuration.emit_lengths_of,
+        );
+    }
+
+    let run_report = RunReport {
+        map_references: configuration
+            .map_coverage
+            .as_ref()
+            .map(|_| MapReferences::new(&context.query_hodeco_maps, &context.target_hodeco_maps)),
+        warnings: configuration
+            .warnings_file
+            .as_ref()
+            .map(|_| Mutex::new(Vec::new())),
+        ..Default::default()
+    };
+    if configuration.input_list.is_some() {
+        let parallel_files = configuration.parallel_files.unwrap_or(1).max(1);
+        info!(
+            "Homopolymer decompressing {} files, {parallel_files} at a time...",
+            inputs.len()
+        );
+        let next_input = AtomicUsize::new(0);
+        crossbeam::scope(|scope| {
+            for thread_id in 0..parallel_files {
+                let (configuration, context, inputs, next_input, run_report) =
+                    (&configuration, &context, &inputs, &next_input, &run_report);
+                scope
+                    .builder()
+                    .name(format!("file_thread_{thread_id}"))
+                    .spawn(move |_| {
+                        while let Some(input) =
+                            inputs.get(next_input.fetch_add(1, Ordering::Relaxed))
+                        {
+                            info!("Homopolymer decompressing {:?}...", input.input);
+                            decompress_input(
+                                configuration,
+                                context,
+                                input,
+                                Some(&input.input),
+                                run_report,
+                            );
+                        }
+                    })
+                    .unwrap_or_else(|error| panic!("Cannot spawn file thread: {error:?}"));
+            }
+        })
+        .unwrap_or_else(|error| panic!("Error: {error:?}"));
+    } else {
+        decompress_input(&configuration, &context, &inputs[0], None, &run_report);
+    }
+
+    if let (Some(map_coverage), Some(map_references)) =
+        (&configuration.map_coverage, &run_report.map_references)
+    {
+        info!("Writing map coverage...");
+        write_map_coverage(map_coverage, configuration.io_buffer_size, map_references);
+    }
+    if let (Some(warnings_file), Some(warnings)) =
+        (&configuration.warnings_file, run_report.warnings)
+    {
+        info!("Writing warnings...");
+        let mut warnings = warnings.into_inner().unwrap();
+        warnings
+            .sort_by(|a, b| (&a.input_file, a.line_number).cmp(&(&b.input_file, b.line_number)));
+        write_warnings(
+            warnings_file,
+            configuration.io_buffer_size,
+            &warnings,
+            configuration.input.as_deref(),
+        );
+    }
+    run_report.statistics.log();
+    let dropped_lines = run_report.dropped_by_allowlist.into_inner();
+    if dropped_lines > 0 {
+        info!("Dropped {dropped_lines} alignments whose query or target is not in its allowlist");
+    }
+    let lines_without_alignment_string = run_report.without_alignment_string.into_inner();
+    if lines_without_alignment_string > 0 {
+        warn!("Found {lines_without_alignment_string} lines without CIGAR and difference string, whose base counts were left in compressed space");
+    }
+    let without_target_offset = run_report.without_target_offset.into_inner();
+    if without_target_offset > 0 {
+        warn!("Found {without_target_offset} lines whose target has no entry in the target offset table, their target coordinates were not offset");
+    }
+    let unsorted_queries = run_report.unsorted_queries.into_inner();
+    if unsorted_queries > 0 {
+        warn!("Found {unsorted_queries} lines whose query sorts before the query of the preceding alignment, the alignments of a query may have been output in multiple groups");
+    }
+    let swapped_roles = run_report.swapped_roles.into_inner();
+    if swapped_roles > 0 {
+        warn!("Found {swapped_roles} lines whose query or target has a hodeco map only among the maps of the other role");
+    }
+    let skipped_lines = run_report.skipped.into_inner();
+    if skipped_lines > 0 {
+        exit_with(
+            ExitCode::LinesSkipped,
+            format!("Skipped {skipped_lines} lines that could not be decompressed"),
+        );
+    }
+    info!("Done");
+}
+
+/// The data loaded from the files given in the [Configuration] that is needed while processing the alignments.
+struct RunContext {
+    /// The hodeco maps of the query sequences, after `--swap-roles` is applied.
+    query_hodeco_maps: HashMap<String, HodecoMap>,
+    /// The hodeco maps of the target sequences, after `--swap-roles` is applied.
+    target_hodeco_maps: HashMap<String, HodecoMap>,
+    /// The sequence names loaded from `--query-allowlist`.
+    query_allowlist_names: Option<HashSet<String>>,
+    /// The sequence names loaded from `--target-allowlist`.
+    target_allowlist_names: Option<HashSet<String>>,
+    /// The entries loaded from `--target-offset-table`.
+    target_offsets: Option<HashMap<String, TargetOffset>>,
+    /// The progress bars if stderr is a terminal.
+    progress_bars: Option<MultiProgress>,
+}
+
+/// Information about the processed lines that is reported at the end.
+#[derive(Default)]
+struct RunReport {
+    /// Lines that could not be decompressed and were skipped.
+    skipped: AtomicUsize,
+    /// Lines without CIGAR and difference string, whose base counts were left in compressed space.
+    without_alignment_string: AtomicUsize,
+    /// Lines referencing each sequence of the hodeco maps, if `--map-coverage` is given.
+    map_references: Option<MapReferences>,
+    /// Warnings about individual lines, if `--warnings-file` is given.
+    warnings: Option<Mutex<Vec<Warning>>>,
+    /// Lines that were dropped because their query or target is not in its allowlist.
+    dropped_by_allowlist: AtomicUsize,
+    /// Lines whose query or target has a hodeco map only in the maps of the other role.
+    swapped_roles: AtomicUsize,
+    /// True if a failing line was dumped for `--dump-first-failure`.
+    failure_dumped: AtomicBool,
+    /// Lines whose target has no entry in the `--target-offset-table`.
+    without_target_offset: AtomicUsize,
+    /// Lines that start a group of `--group-by-query` with a query that sorts before the query of the previous group.
+    unsorted_queries: AtomicUsize,
+    /// Statistics about the alignments that were output.
+    statistics: DecompressionStatistics,
+}
+
+/// Statistics about the decompressed alignments that were output, i.e. not skipped or filtered out.
+#[derive(Default)]
+struct DecompressionStatistics {
+    alignments: AtomicUsize,
+    /// Alignments split by `--split-on-gap` count as multiple records.
+    records: AtomicUsize,
+    /// The total compressed and decompressed lengths of the query ranges of the alignments.
+    query_lengths: [AtomicUsize; 2],
+    /// The total compressed and decompressed lengths of the target ranges of the alignments.
+    target_lengths: [AtomicUsize; 2],
+}
+
+impl DecompressionStatistics {
+    /// Adds an alignment with the given number of records and the given compressed and decompressed lengths.
+    fn add(&self, records: usize, query_lengths: [usize; 2], target_lengths: [usize; 2]) {
+        self.alignments.fetch_add(1, Ordering::Relaxed);
+        self.records.fetch_add(records, Ordering::Relaxed);
+        for (total, length) in self
+            .query_lengths
+            .iter()
+            .zip(query_lengths)
+            .chain(self.target_lengths.iter().zip(target_lengths))
+        {
+            total.fetch_add(length, Ordering::Relaxed);
+        }
+    }
+
+    fn log(&self) {
+        info!(
+            "Decompressed {} alignments into {} records",
+            self.alignments.load(Ordering::Relaxed),
+            self.records.load(Ordering::Relaxed)
+        );
+        for (role, lengths) in [
+            ("query", &self.query_lengths),
+            ("target", &self.target_lengths),
+        ] {
+            let [compressed, decompressed] =
+                [&lengths[0], &lengths[1]].map(|length| length.load(Ordering::Relaxed));
+            let ratio = if compressed > 0 {
+                decompressed as f64 / compressed as f64
+            } else {
+                0.0
+            };
+            info!(
+                "Aligned {role} bases expanded from {compressed} to {decompressed} ({ratio:.3}x)"
+            );
+        }
+    }
+}
+
+impl RunReport {
+    /// Records a warning for `--warnings-file`.
+    /// The warning is not logged, this is left to the caller.
+    fn record_warning(
+        &self,
+        line_number: LineNumber,
+        sequence_name: &str,
+        warning_type: &'static str,
+        message: String,
+    ) {
+        if let Some(warnings) = &self.warnings {
+            warnings.lock().unwrap().push(Warning {
+                input_file: line_number.input_name.map(Path::to_path_buf),
+                line_number: line_number.number,
+                sequence_name: sequence_name.to_string(),
+                warning_type,
+                message,
+            });
+        }
+    }
+}
+
+/// A warning about a line of the input, for `--warnings-file`.
+struct Warning {
+    /// `None` if there is only a single input file.
+    input_file: Option<PathBuf>,
+    line_number: usize,
+    sequence_name: String,
+    warning_type: &'static str,
+    message: String,
+}
+
+/// Writes the warnings for `--warnings-file`.
+/// Warnings without input file are attributed to `single_input`.
+fn write_warnings(
+    path: &Path,
+    io_buffer_size: usize,
+    warnings: &[Warning],
+    single_input: Option<&Path>,
+) {
+    let warnings_file = File::create(path).unwrap_or_else(|error| {
+        exit_with(
+            ExitCode::IoError,
+            format!("Cannot open warnings file: {error:?}"),
+        )
+    });
+    let mut warnings_writer = BufWriter::with_capacity(io_buffer_size, warnings_file);
+    for warning in warnings {
+        let input_file = warning
+            .input_file
+            .as_deref()
+            .or(single_input)
+            .map(|input_file| input_file.display().to_string())
+            .unwrap_or_default();
+        writeln!(
+            warnings_writer,
+            "{input_file}\t{}\t{}\t{}\t{}",
+            warning.line_number, warning.sequence_name, warning.warning_type, warning.message
+        )
+        .unwrap_or_else(|error| {
+            exit_with(
+                ExitCode::IoError,
+                format!("Cannot write warnings file: {error:?}"),
+            )
+        });
+    }
+    warnings_writer.flush().unwrap_or_else(|error| {
+        exit_with(
+            ExitCode::IoError,
+            format!("Cannot flush warnings file: {error:?}"),
+        )
+    });
+}
+
+/// The number of alignments referencing each sequence of the hodeco maps.
+/// The counters are created for all sequences up front, so they can be incremented concurrently.
+struct MapReferences {
+    query: HashMap<String, AtomicUsize>,
+    target: HashMap<String, AtomicUsize>,
+}
+
+impl MapReferences {
+    fn new(
+        query_hodeco_maps: &HashMap<String, HodecoMap>,
+        target_hodeco_maps: &HashMap<String, HodecoMap>,
+    ) -> Self {
+        let counters = |hodeco_maps: &HashMap<String, HodecoMap>| {
+            hodeco_maps
+                .keys()
+                .map(|sequence_name| (sequence_name.clone(), AtomicUsize::new(0)))
+                .collect()
+        };
+        Self {
+            query: counters(query_hodeco_maps),
+            target: counters(target_hodeco_maps),
+        }
+    }
+
+    /// Counts the sequences referenced by the given alignment.
+    /// Sequences without hodeco map are ignored.
+    fn count(&self, paf_line: &PAFLine) {
+        if let Some(counter) = self.query.get(&paf_line.query_sequence_name) {
+            counter.fetch_add(1, Ordering::Relaxed);
+        }
+        if let Some(counter) = self.target.get(&paf_line.target_sequence_name) {
+            counter.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+fn write_map_coverage(path: &Path, io_buffer_size: usize, map_references: &MapReferences) {
+    let map_coverage_file = File::create(path).unwrap_or_else(|error| {
+        exit_with(
+            ExitCode::IoError,
+            format!("Cannot open map coverage file: {error:?}"),
+        )
+    });
+    let mut map_coverage_writer = BufWriter::with_capacity(io_buffer_size, map_coverage_file);
+    for (role, counters) in [
+        ("query", &map_references.query),
+        ("target", &map_references.target),
+    ] {
+        let counters: BTreeMap<_, _> = counters.iter().collect();
+        for (sequence_name, counter) in counters {
+            let times_referenced = counter.load(Ordering::Relaxed);
+            writeln!(
+                map_coverage_writer,
+                "{sequence_name}\t{role}\t{times_referenced}"
+            )
+            .unwrap_or_else(|error| {
+                exit_with(
+                    ExitCode::IoError,
+                    format!("Cannot write map coverage file: {error:?}"),
+                )
+            });
+        }
+    }
+    map_coverage_writer.flush().unwrap_or_else(|error| {
+        exit_with(
+            ExitCode::IoError,
+            format!("Cannot flush map coverage file: {error:?}"),
+        )
+    });
+}
+
+/// An input file together with its output file.
+struct Input {
+    input: PathBuf,
+    /// `None` with `--validate-only`.
+    output: Option<PathBuf>,
+}
 
-    /// The number of compute threads to use for decompression.
-    /// Note that the input and output threads are not counted under this number.
-    #[clap(long, default_value = "1")]
-    compute_threads: usize,
+/// Reads the input and output files listed in the file given by `--input-list`.
+fn read_input_list(input_list: &Path, without_output: bool) -> Vec<Input> {
+    let input_list = std::fs::read_to_string(input_list).unwrap_or_else(|error| {
+        exit_with(
+            ExitCode::IoError,
+            format!("Cannot read input list: {error:?}"),
+        )
+    });
 
-    /// The level of log messages to be produced.
-    #[clap(long, default_value = "Info")]
-    log_level: LevelFilter,
+    input_list
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.is_empty())
+        .map(|(line_index, line)| {
+            let mut columns = line.split('\t');
+            let input = columns.next().unwrap_or_default();
+            let output = columns.next();
+            let expected_columns = if without_output { 1 } else { 2 };
+            let actual_columns = 1 + usize::from(output.is_some()) + columns.count();
+            if actual_columns != expected_columns {
+                exit_with(
+                    ExitCode::InvalidArguments,
+                    format!(
+                        "Line {} of the input list has {actual_columns} columns, but expected {expected_columns}",
+                        line_index + 1
+                    ),
+                );
+            }
+            Input {
+                input: input.into(),
+                output: output.map(Into::into),
+            }
+        })
+        .collect()
 }
 
-fn initialise_logging(log_level: &LevelFilter) {
-    TermLogger::init(
-        *log_level,
-        Default::default(),
-        TerminalMode::Stderr,
-        ColorChoice::Auto,
-    )
-    .unwrap();
-    info!("Logging initialised successfully")
+/// Decompresses a single input file into its output file.
+/// If `input_name` is given, then it is used to distinguish the log messages of this file from those of other files.
+fn decompress_input(
+    configuration: &Configuration,
+    context: &RunContext,
+    input: &Input,
+    input_name: Option<&Path>,
+    run_report: &RunReport,
+) {
+    info!("Opening files...");
+    let input_file = File::open(&input.input).unwrap_or_else(|error| {
+        exit_with(
+            ExitCode::IoError,
+            format!("Cannot open input file {:?}: {error:?}", input.input),
+        )
+    });
+    let output_file = if let Some(output) = &input.output {
+        let output_file = File::create(output).unwrap_or_else(|error| {
+            exit_with(
+                ExitCode::IoError,
+                format!("Cannot open output file {output:?}: {error:?}"),
+            )
+        });
+        if configuration.bgzf || output.extension() == Some(OsStr::new("bgz")) {
+            OutputFile::Bgzf(bgzf::Writer::new(output_file))
+        } else {
+            OutputFile::Plain(output_file)
+        }
+    } else {
+        OutputFile::Discarded(io::sink())
+    };
+    let original_output_file = configuration
+        .original_output
+        .as_ref()
+        .map(|original_output| {
+            File::create(original_output).unwrap_or_else(|error| {
+                exit_with(
+                    ExitCode::IoError,
+                    format!("Cannot open original output file: {error:?}"),
+                )
+            })
+        });
+    let output_writers = OutputWriters::new(output_file, original_output_file, configuration);
+
+    if configuration.compute_threads == 0 {
+        info!("Homopolymer decompressing on the main thread...");
+        decompress_single_threaded(
+            configuration,
+            context,
+            input_file,
+            input_name,
+            output_writers,
+            run_report,
+        );
+    } else {
+        info!("Homopolymer decompressing...");
+        decompress_multi_threaded(
+            configuration,
+            context,
+            input_file,
+            input_name,
+            output_writers,
+            run_report,
+        );
+    }
 }
 
-fn main() {
-    let configuration = Configuration::parse();
-    initialise_logging(&configuration.log_level);
+/// Reads, decompresses and writes all PAF lines on the current thread.
+fn decompress_single_threaded(
+    configuration: &Configuration,
+    context: &RunContext,
+    input_file: File,
+    input_name: Option<&Path>,
+    mut output_writers: OutputWriters,
+    run_report: &RunReport,
+) {
+    read_input_blocks(
+        configuration,
+        context,
+        input_file,
+        input_name,
+        run_report,
+        |input_item| match input_item {
+            InputItem::Comment(comment) => output_writers.write(OutputItem::Comment(comment)),
+            InputItem::Block(block) => {
+                if let Some(output_item) = process_block(block, configuration, context, run_report)
+                {
+                    output_writers.write(output_item);
+                }
+            }
+        },
+    );
+    output_writers.finish();
+}
 
-    info!("Opening files...");
-    let input_file = File::open(&configuration.input)
-        .unwrap_or_else(|error| panic!("Cannot open input file: {error:?}"));
-    let output_file = File::create(&configuration.output)
-        .unwrap_or_else(|error| panic!("Cannot open output file: {error:?}"));
-
-    let query_hodeco_map_file = File::open(&configuration.query_hodeco_map)
-        .unwrap_or_else(|error| panic!("Cannot open query hodeco map file: {error:?}"));
-    let query_hodeco_map_reader =
-        BufReader::with_capacity(configuration.io_buffer_size, query_hodeco_map_file);
-    let mut query_hodeco_map_decoder = Decoder::from_reader(query_hodeco_map_reader);
-
-    let target_hodeco_map_file = File::open(&configuration.target_hodeco_map)
-        .unwrap_or_else(|error| panic!("Cannot open target hodeco map file: {error:?}"));
-    let target_hodeco_map_reader =
-        BufReader::with_capacity(configuration.io_buffer_size, target_hodeco_map_file);
-    let mut target_hodeco_map_decoder = Decoder::from_reader(target_hodeco_map_reader);
+/// An item read from the input that is handed on for processing.
+enum InputItem<'input> {
+    /// A comment line that is passed through according to `--passthrough-comments`.
+    Comment(String),
+    /// A block of alignments to be decompressed.
+    Block(Block<'input>),
+}
 
-    info!("Loading hodeco maps...");
-    let query_hodeco_maps: HashMap<_, _> = query_hodeco_map_decoder
-        .decode::<(String, Vec<usize>)>()
-        .map(|result| match result {
-            Ok(item) => item,
-            Err(error) => panic!("Cannot read hodeco map: {error:?}"),
-        })
-        .collect();
-    let target_hodeco_maps: HashMap<_, _> = target_hodeco_map_decoder
-        .decode::<(String, Vec<usize>)>()
-        .map(|result| match result {
-            Ok(item) => item,
-            Err(error) => panic!("Cannot read hodeco map: {error:?}"),
-        })
-        .collect();
+/// Reads all PAF lines from the input, groups the alignments into blocks
+/// and hands each block and each passed-through comment line to `handle_input_item` in input order.
+fn read_input_blocks<'input>(
+    configuration: &Configuration,
+    context: &RunContext,
+    input_file: File,
+    input_name: Option<&'input Path>,
+    run_report: &RunReport,
+    mut handle_input_item: impl FnMut(InputItem<'input>),
+) {
+    let input_file_reader = BufReader::with_capacity(configuration.io_buffer_size, input_file);
+    let mut sortedness_check = SortednessCheck::default();
+    let mut block_builder = BlockBuilder::new(configuration);
+    let mut progress = processing_progress(configuration, context, input_name);
+    for (line_index, line) in input_file_reader.lines().enumerate() {
+        progress.advance(1);
+        let line_number = LineNumber {
+            input_name,
+            number: line_index + 1,
+        };
+        let (paf_line, original) =
+            match read_input_line(line, line_number, configuration, run_report) {
+                InputLine::Comment(comment) => {
+                    if configuration.passthrough_comments {
+                        handle_input_item(InputItem::Comment(comment));
+                    }
+                    continue;
+                }
+                InputLine::Alignment(paf_line, original) => (*paf_line, original),
+                InputLine::Skipped => continue,
+            };
+        if configuration.assert_sorted {
+            sortedness_check.check(&paf_line, line_number);
+        }
+        if let Some(block) = block_builder.push((line_number, paf_line, original), run_report) {
+            handle_input_item(InputItem::Block(block));
+        }
+    }
+    if let Some(block) = block_builder.finish() {
+        handle_input_item(InputItem::Block(block));
+    }
+    progress.finish();
+}
 
-    info!("Homopolymer decompressing...");
+/// Reads, decompresses and writes all PAF lines using separate threads for input, output and each compute thread.
+fn decompress_multi_threaded(
+    configuration: &Configuration,
+    context: &RunContext,
+    input_file: File,
+    input_name: Option<&Path>,
+    output_writers: OutputWriters,
+    run_report: &RunReport,
+) {
     crossbeam::scope(|scope| {
         let (input_sender, input_receiver) = channel::bounded(configuration.queue_size);
+        let (output_sender, output_receiver) =
+            channel::bounded::<OutputItem>(configuration.queue_size);
+        let comment_sender = output_sender.clone();
         scope
             .builder()
             .name("input_thread".to_string())
             .spawn(move |_| {
-                let input_file_reader =
-                    BufReader::with_capacity(configuration.io_buffer_size, input_file);
-                for line in input_file_reader.lines() {
-                    let line =
-                        line.unwrap_or_else(|error| panic!("Cannot read PAF line: {error:?}"));
-                    let mut line = line.as_str();
-                    let paf_line = parse_line(&mut line)
-                        .unwrap_or_else(|error| panic!("Cannot parse PAF line: {error:?}"));
-                    assert!(line.is_empty(), "Line was not parsed completely");
-                    input_sender
-                        .send(paf_line)
-                        .unwrap_or_else(|error| panic!("Cannot send PAF line: {error:?}"));
-                }
+                read_input_blocks(
+                    configuration,
+                    context,
+                    input_file,
+                    input_name,
+                    run_report,
+                    |input_item| match input_item {
+                        InputItem::Comment(comment) => comment_sender
+                            .send(OutputItem::Comment(comment))
+                            .unwrap_or_else(|error| panic!("Cannot send comment line: {error:?}")),
+                        InputItem::Block(block) => input_sender
+                            .send(block)
+                            .unwrap_or_else(|error| panic!("Cannot send PAF lines: {error:?}")),
+                    },
+                );
             })
             .unwrap_or_else(|error| panic!("Cannot spawn input thread: {error:?}"));
 
-        let (output_sender, output_receiver) = channel::bounded::<String>(configuration.queue_size);
         scope
             .builder()
             .name("output_thread".to_string())
             .spawn(move |_| {
-                let mut output_file_writer =
-                    BufWriter::with_capacity(configuration.io_buffer_size, output_file);
-                while let Ok(hodeco_paf_line) = output_receiver.recv() {
-                    output_file_writer
-                        .write_all(hodeco_paf_line.as_bytes())
-                        .unwrap_or_else(|error| panic!("Cannot write PAF line: {error:?}"));
-                    output_file_writer
-                        .write_all(&[b'\n'])
-                        .unwrap_or_else(|error| panic!("Cannot write line feed: {error:?}"));
+                let mut output_writers = output_writers;
+                while let Ok(output_item) = output_receiver.recv() {
+                    output_writers.write(output_item);
                 }
+                output_writers.finish();
             })
             .unwrap_or_else(|error| panic!("Cannot spawn input thread: {error:?}"));
 
         for thread_id in 0..configuration.compute_threads {
-            let query_hodeco_maps = &query_hodeco_maps;
-            let target_hodeco_maps = &target_hodeco_maps;
             let input_receiver = input_receiver.clone();
             let output_sender = output_sender.clone();
             scope
                 .builder()
                 .name(format!("compute_thread_{thread_id}"))
                 .spawn(move |_| {
-                    while let Ok(paf_line) = input_receiver.recv() {
-                        let hodeco_paf_line =
-                            hodeco_paf_line(paf_line, query_hodeco_maps, target_hodeco_maps);
-                        let hodeco_paf_line = hodeco_paf_line.to_string();
-                        output_sender
-                            .send(hodeco_paf_line)
-                            .unwrap_or_else(|error| panic!("Cannot send PAF line: {error:?}"));
+                    while let Ok(block) = input_receiver.recv() {
+                        if let Some(output_item) =
+                            process_block(block, configuration, context, run_report)
+                        {
+                            output_sender
+                                .send(output_item)
+                                .unwrap_or_else(|error| panic!("Cannot send PAF line: {error:?}"));
+                        }
                     }
                 })
                 .unwrap_or_else(|error| panic!("Cannot spawn input thread: {error:?}"));
@@ -160,216 +1865,1043 @@ fn main() {
         info!("Waiting for threads to join...");
     })
     .unwrap_or_else(|error| panic!("Error: {error:?}"));
+}
 
-    info!("Done");
+/// Creates the progress reporter for processing the input lines.
+fn processing_progress(
+    configuration: &Configuration,
+    context: &RunContext,
+    input_name: Option<&Path>,
+) -> Progress {
+    let phase = if let Some(input_name) = input_name {
+        format!("processing alignments of {}", input_name.display())
+    } else {
+        "processing alignments".to_string()
+    };
+    Progress::new(
+        phase,
+        "lines",
+        None,
+        configuration.progress_interval.map(Duration::from_secs),
+        context.progress_bars.as_ref(),
+    )
 }
 
-fn hodeco_paf_line(
-    mut hoco_paf: PAFLine,
-    query_hodeco_maps: &HashMap<String, Vec<usize>>,
-    target_hodeco_maps: &HashMap<String, Vec<usize>>,
-) -> PAFLine {
-    let query_hodeco_map = query_hodeco_maps
-        .get(&hoco_paf.query_sequence_name)
-        .unwrap_or_else(|| {
-            panic!(
-                "Query hodeco map not found: {}",
-                hoco_paf.query_sequence_name
-            )
-        });
-    let target_hodeco_map = target_hodeco_maps
-        .get(&hoco_paf.target_sequence_name)
-        .unwrap_or_else(|| {
-            panic!(
-                "Target hodeco map not found: {}",
-                hoco_paf.target_sequence_name
-            )
-        });
+/// The number of a line of the input PAF file, which is displayed together with the input file name if there is one.
+#[derive(Clone, Copy)]
+struct LineNumber<'input> {
+    input_name: Option<&'input Path>,
+    number: usize,
+}
 
-    let hoco_query_start = hoco_paf.query_start_coordinate;
-    let hoco_target_start = hoco_paf.target_start_coordinate_on_original_strand;
-    let hoco_query_sequence_length = hoco_paf.query_sequence_length;
-
-    assert_eq!(hoco_paf.query_sequence_length, query_hodeco_map.len() - 1);
-    assert_eq!(hoco_paf.target_sequence_length, target_hodeco_map.len() - 1);
-    hoco_paf.query_sequence_length = *query_hodeco_map.last().unwrap();
-    hoco_paf.target_sequence_length = *target_hodeco_map.last().unwrap();
-
-    hoco_paf.query_start_coordinate = query_hodeco_map[hoco_paf.query_start_coordinate];
-    hoco_paf.query_end_coordinate = query_hodeco_map[hoco_paf.query_end_coordinate];
-    hoco_paf.target_start_coordinate_on_original_strand =
-        target_hodeco_map[hoco_paf.target_start_coordinate_on_original_strand];
-    hoco_paf.target_end_coordinate_on_original_strand =
-        target_hodeco_map[hoco_paf.target_end_coordinate_on_original_strand];
-    assert!(hoco_paf.query_end_coordinate as isize - hoco_paf.query_start_coordinate as isize > 0);
-    assert!(
-        hoco_paf.target_end_coordinate_on_original_strand as isize
-            - hoco_paf.target_start_coordinate_on_original_strand as isize
-            > 0
-    );
+impl Display for LineNumber<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.number)?;
+        if let Some(input_name) = self.input_name {
+            write!(f, " of {}", input_name.display())?;
+        }
+        Ok(())
+    }
+}
 
-    let query_alignment_length = hoco_paf.query_end_coordinate - hoco_paf.query_start_coordinate;
-    let target_alignment_length = hoco_paf.target_end_coordinate_on_original_strand
-        - hoco_paf.target_start_coordinate_on_original_strand;
-
-    if let Some(cigar_string) = &mut hoco_paf.cigar_string {
-        let mut number_of_matching_bases = 0;
-        let mut number_of_bases_and_gaps = 0;
-
-        let mut query_offset = hoco_query_start;
-        let mut target_offset = hoco_target_start;
-
-        for cigar_column in &mut cigar_string.0 {
-            match cigar_column {
-                CigarColumn::Match(count) => {
-                    let query_limit = query_offset + *count;
-                    let target_limit = target_offset + *count;
-                    let hodeco_count =
-                        query_hodeco_map[query_limit] - query_hodeco_map[query_offset];
-                    query_offset = query_limit;
-                    target_offset = target_limit;
-                    *count = hodeco_count;
-                    number_of_matching_bases += *count;
-                }
-                CigarColumn::Deletion(count) => {
-                    let target_limit = target_offset + *count;
-                    let hodeco_count =
-                        target_hodeco_map[target_limit] - target_hodeco_map[target_offset];
-                    target_offset = target_limit;
-                    *count = hodeco_count;
-                }
-                CigarColumn::Insertion(count) => {
-                    let query_limit = query_offset + *count;
-                    let hodeco_count =
-                        query_hodeco_map[query_limit] - query_hodeco_map[query_offset];
-                    query_offset = query_limit;
-                    *count = hodeco_count;
+/// A line of the input PAF file.
+enum InputLine {
+    /// A comment or header line, starting with `#`.
+    Comment(String),
+    /// An alignment, with its original text if `--emit-original` is given.
+    Alignment(Box<PAFLine>, Option<String>),
+    /// An alignment that could not be parsed and is skipped according to `--on-error`.
+    Skipped,
+}
+
+/// Reads a line from the input PAF file, and parses it unless it is a comment.
+fn read_input_line(
+    line: io::Result<String>,
+    line_number: LineNumber,
+    configuration: &Configuration,
+    run_report: &RunReport,
+) -> InputLine {
+    let line = line.unwrap_or_else(|error| {
+        exit_with(
+            ExitCode::IoError,
+            format!("Cannot read PAF line {line_number}: {error:?}"),
+        )
+    });
+
+    if line.starts_with('#') {
+        InputLine::Comment(line)
+    } else {
+        let paf_line = parse_paf_line(&line)
+            .map(Some)
+            .map_err(LineError::Malformed);
+        if let (Err(error), true) = (&paf_line, configuration.dump_first_failure) {
+            dump_first_failure(line_number, Some(&line), None, error, run_report);
+        }
+        let paf_line = if let Some(paf_line) =
+            handle_error(paf_line, line_number, configuration, run_report)
+        {
+            Box::new(paf_line)
+        } else {
+            return InputLine::Skipped;
+        };
+        let original = if configuration.emit_original || configuration.dump_first_failure {
+            Some(line)
+        } else {
+            None
+        };
+        InputLine::Alignment(paf_line, original)
+    }
+}
+
+/// Parses an alignment line of the input PAF file.
+/// Returns an error if the line is malformed or was not parsed completely, with the unparsed remainder in the latter case.
+fn parse_paf_line(mut line: &str) -> Result<PAFLine, String> {
+    let paf_line =
+        parse_line(&mut line).map_err(|error| format!("cannot parse line: {error:?}"))?;
+    if line.is_empty() {
+        Ok(paf_line)
+    } else {
+        Err(format!(
+            "line was not parsed completely, unparsed remainder: {line:?}"
+        ))
+    }
+}
+
+/// Alignments that are processed together, each with its line number and its original text if `--emit-original` is given.
+type Block<'input> = Vec<(LineNumber<'input>, PAFLine, Option<String>)>;
+
+/// Collects consecutive alignments of the same query into blocks for `--group-by-query`.
+/// Without `--group-by-query`, each alignment is a block of its own.
+struct BlockBuilder<'input> {
+    group_by_query: bool,
+    block: Block<'input>,
+    /// The query of the last completed block, to detect input that is not sorted by query.
+    previous_query_sequence_name: String,
+}
+
+impl<'input> BlockBuilder<'input> {
+    fn new(configuration: &Configuration) -> Self {
+        Self {
+            group_by_query: configuration.group_by_query,
+            block: Vec::new(),
+            previous_query_sequence_name: String::new(),
+        }
+    }
+
+    /// Adds an alignment, and returns the block it completes, if any.
+    fn push(
+        &mut self,
+        alignment: (LineNumber<'input>, PAFLine, Option<String>),
+        run_report: &RunReport,
+    ) -> Option<Block<'input>> {
+        if !self.group_by_query {
+            return Some(vec![alignment]);
+        }
+
+        let completed_block = match self.block.first() {
+            Some((_, first, _)) if first.query_sequence_name != alignment.1.query_sequence_name => {
+                self.previous_query_sequence_name
+                    .clone_from(&first.query_sequence_name);
+                if alignment.1.query_sequence_name < self.previous_query_sequence_name {
+                    let message = format!(
+                        "query sorts before query {} of the preceding alignment, so its alignments may be output in multiple groups",
+                        self.previous_query_sequence_name
+                    );
+                    if run_report.unsorted_queries.fetch_add(1, Ordering::Relaxed) == 0 {
+                        warn!(
+                            "Input is not sorted by query: the {message}: line {}, query {}. Further such lines are only counted",
+                            alignment.0, alignment.1.query_sequence_name
+                        );
+                    }
+                    run_report.record_warning(
+                        alignment.0,
+                        &alignment.1.query_sequence_name,
+                        "query-not-sorted",
+                        message,
+                    );
                 }
-                CigarColumn::Mismatch(_) => panic!("Mismatch not supported in CIGAR"),
+                Some(mem::take(&mut self.block))
             }
+            _ => None,
+        };
+        self.block.push(alignment);
+        completed_block
+    }
+
+    /// Returns the last block, if any.
+    fn finish(self) -> Option<Block<'input>> {
+        if self.block.is_empty() {
+            None
+        } else {
+            Some(self.block)
+        }
+    }
+}
 
-            match cigar_column {
-                CigarColumn::Match(count)
-                | CigarColumn::Deletion(count)
-                | CigarColumn::Insertion(count)
-                | CigarColumn::Mismatch(count) => number_of_bases_and_gaps += *count,
+/// Decompresses a block of alignments into output records.
+/// Returns `None` if all alignments were skipped or filtered out.
+fn process_block(
+    block: Block,
+    configuration: &Configuration,
+    context: &RunContext,
+    run_report: &RunReport,
+) -> Option<OutputItem> {
+    let mut records = Vec::new();
+    let mut block_original = None;
+    for (line_number, paf_line, original) in block {
+        // Only clone the line while no failure was dumped yet.
+        let dumped_paf_line = if configuration.dump_first_failure
+            && !run_report.failure_dumped.load(Ordering::Relaxed)
+        {
+            Some(paf_line.clone())
+        } else {
+            None
+        };
+        let processed = process_paf_line(paf_line, line_number, configuration, context, run_report)
+            .map(Some)
+            .map_err(LineError::Decompression);
+        if let (Err(error), Some(paf_line)) = (&processed, &dumped_paf_line) {
+            dump_first_failure(
+                line_number,
+                original.as_deref(),
+                Some(describe_failing_line(
+                    paf_line,
+                    &context.query_hodeco_maps,
+                    &context.target_hodeco_maps,
+                )),
+                error,
+                run_report,
+            );
+        }
+        if let Some(line_records) = handle_error(processed, line_number, configuration, run_report)
+        {
+            records.extend(line_records);
+            // The original line is also kept for `--dump-first-failure`, but only output with `--emit-original`.
+            if configuration.emit_original {
+                block_original = original;
             }
         }
+    }
 
-        hoco_paf.number_of_matching_bases = number_of_matching_bases;
-        hoco_paf.number_of_bases_and_gaps = number_of_bases_and_gaps;
+    if records.is_empty() {
+        return None;
     }
+    if configuration.group_by_query {
+        // The sort is stable, so records with the same start stay in input order.
+        records.sort_by_key(|(query_start_coordinate, _)| *query_start_coordinate);
+    }
+    Some(OutputItem::Records {
+        records: records.into_iter().map(|(_, record)| record).collect(),
+        original: block_original,
+    })
+}
 
-    if let Some(difference_string) = &mut hoco_paf.difference_string {
-        let mut total_number_of_mismatches_and_gaps = 0;
+/// An error that occurred while processing a line of the input PAF file.
+enum LineError {
+    /// The line could not be parsed.
+    Malformed(String),
+    /// The line could not be decompressed.
+    Decompression(HodecoError),
+}
 
-        let mut query_hodeco_len = 0;
-        let mut target_hodeco_len = 0;
+impl LineError {
+    fn kind(&self) -> &'static str {
+        match self {
+            LineError::Malformed(_) => "malformed-line",
+            LineError::Decompression(error) => error.kind(),
+        }
+    }
 
-        let mut query_offset = hoco_query_start;
-        let mut target_offset = hoco_target_start;
-        let mut mismatch_insertion = Vec::new();
+    /// Returns the name of the sequence this error is about, or `*` if the line could not be parsed.
+    fn sequence_name(&self) -> &str {
+        match self {
+            LineError::Malformed(_) => "*",
+            LineError::Decompression(error) => error.sequence_name(),
+        }
+    }
+}
 
-        for (index, difference_column) in difference_string.0.iter_mut().enumerate() {
-            match difference_column {
-                DifferenceColumn::Match { length } => {
-                    let query_limit = query_offset + *length;
-                    let target_limit = target_offset + *length;
-                    let hodeco_count =
-                        query_hodeco_map[query_limit] - query_hodeco_map[query_offset];
-                    query_offset = query_limit;
-                    target_offset = target_limit;
-                    *length = hodeco_count;
+impl Display for LineError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LineError::Malformed(message) => write!(f, "{message}"),
+            LineError::Decompression(error) => write!(f, "{error}"),
+        }
+    }
+}
 
-                    query_hodeco_len += hodeco_count;
-                    target_hodeco_len += hodeco_count;
-                }
-                DifferenceColumn::Deletion {
-                    missing_query_characters,
-                } => {
-                    let target_limit = target_offset + missing_query_characters.len();
-                    *missing_query_characters = homopolymer_decompress_string(
-                        missing_query_characters,
-                        &target_hodeco_map[target_offset..target_limit + 1],
-                    );
-                    target_offset = target_limit;
-                    total_number_of_mismatches_and_gaps += missing_query_characters.len();
+/// Prints the full context of a line that failed to stderr, if it is the first one, for `--dump-first-failure`.
+/// The description of the parsed line is given if the line could be parsed.
+fn dump_first_failure(
+    line_number: LineNumber,
+    raw_line: Option<&str>,
+    parsed_line_description: Option<String>,
+    error: &LineError,
+    run_report: &RunReport,
+) {
+    if run_report.failure_dumped.swap(true, Ordering::Relaxed) {
+        return;
+    }
 
-                    target_hodeco_len += missing_query_characters.len();
-                }
-                DifferenceColumn::Insertion {
-                    superfluous_query_characters,
-                } => {
-                    let query_limit = query_offset + superfluous_query_characters.len();
-                    *superfluous_query_characters = homopolymer_decompress_string(
-                        superfluous_query_characters,
-                        &query_hodeco_map[query_offset..query_limit + 1],
-                    );
-                    query_offset = query_limit;
-                    total_number_of_mismatches_and_gaps += superfluous_query_characters.len();
+    let mut dump = format!("First failure in PAF line {line_number}\n");
+    if let Some(raw_line) = raw_line {
+        dump.push_str(&format!("Input line: {raw_line}\n"));
+    }
+    if let Some(parsed_line_description) = parsed_line_description {
+        dump.push_str(&parsed_line_description);
+    }
+    dump.push_str(&format!("Error ({}): {error}", error.kind()));
+    eprintln!("{dump}");
+}
 
-                    query_hodeco_len += superfluous_query_characters.len();
-                }
-                DifferenceColumn::Mismatch { reference, query } => {
-                    let query_limit = query_offset + 1;
-                    let target_limit = target_offset + 1;
-                    let hodeco_count =
-                        query_hodeco_map[query_limit] - query_hodeco_map[query_offset] - 1;
-                    query_offset = query_limit;
-                    target_offset = target_limit;
-                    mismatch_insertion.push((index, hodeco_count, *reference, *query));
-                    total_number_of_mismatches_and_gaps += hodeco_count;
-
-                    query_hodeco_len += hodeco_count;
-                    target_hodeco_len += hodeco_count;
-                }
-            }
+/// Describes a parsed PAF line for `--dump-first-failure`,
+/// including the hodeco map entries around its start and end coordinates.
+fn describe_failing_line(
+    paf_line: &PAFLine,
+    query_hodeco_maps: &HashMap<String, HodecoMap>,
+    target_hodeco_maps: &HashMap<String, HodecoMap>,
+) -> String {
+    let mut description = format!("Parsed line: {paf_line:#?}\n");
+    for (role, sequence_name, hodeco_maps, start, end) in [
+        (
+            "query",
+            &paf_line.query_sequence_name,
+            query_hodeco_maps,
+            paf_line.query_start_coordinate,
+            paf_line.query_end_coordinate,
+        ),
+        (
+            "target",
+            &paf_line.target_sequence_name,
+            target_hodeco_maps,
+            paf_line.target_start_coordinate_on_original_strand,
+            paf_line.target_end_coordinate_on_original_strand,
+        ),
+    ] {
+        let hodeco_map = if let Some(hodeco_map) = hodeco_maps.get(sequence_name) {
+            hodeco_map
+        } else {
+            description.push_str(&format!(
+                "There is no {role} hodeco map of {sequence_name}\n"
+            ));
+            continue;
+        };
+        description.push_str(&format!(
+            "The {role} hodeco map of {sequence_name} has compressed length {} and decompressed length {}\n",
+            hodeco_map.total_compressed(),
+            hodeco_map.total_expanded()
+        ));
+        for (coordinate_name, coordinate) in [("start", start), ("end", end)] {
+            let window_end = (coordinate + 3).min(hodeco_map.total_compressed());
+            let entries: Vec<_> = (coordinate.saturating_sub(3)..=window_end)
+                .map(|offset| format!("{offset}:{}", hodeco_map.expand(offset)))
+                .collect();
+            description.push_str(&format!(
+                "  entries around {role} {coordinate_name} {coordinate}: {}\n",
+                entries.join(" ")
+            ));
         }
+    }
+    description
+}
 
-        for (index, hodeco_count, reference, query) in mismatch_insertion.into_iter().rev() {
-            for _ in 0..hodeco_count {
-                difference_string
-                    .0
-                    .insert(index, DifferenceColumn::Mismatch { reference, query });
+/// Applies the `--on-error` policy to the result of processing a line.
+/// Returns the processed line if there was no error, and `None` if the line was skipped.
+fn handle_error<Processed>(
+    processed: Result<Option<Processed>, LineError>,
+    line_number: LineNumber,
+    configuration: &Configuration,
+    run_report: &RunReport,
+) -> Option<Processed> {
+    match processed {
+        Ok(processed) => processed,
+        Err(error) => match configuration.on_error {
+            ErrorPolicy::Abort => exit_with(
+                ExitCode::ValidationFailed,
+                format!("Cannot decompress PAF line {line_number}: {error}"),
+            ),
+            ErrorPolicy::Skip => {
+                warn!("Skipping PAF line {line_number}: {error}");
+                run_report.skipped.fetch_add(1, Ordering::Relaxed);
+                run_report.record_warning(
+                    line_number,
+                    error.sequence_name(),
+                    error.kind(),
+                    error.to_string(),
+                );
+                None
             }
+        },
+    }
+}
+
+/// Warns if the query or target of a PAF line has no hodeco map of its role, but one of the other role,
+/// which indicates that the roles of the hodeco maps are reversed.
+/// The missing map itself is reported by the decompression.
+fn check_roles(
+    paf_line: &PAFLine,
+    line_number: LineNumber,
+    query_hodeco_maps: &HashMap<String, HodecoMap>,
+    target_hodeco_maps: &HashMap<String, HodecoMap>,
+    run_report: &RunReport,
+) {
+    let (role, other_role, sequence_name) = if !query_hodeco_maps
+        .contains_key(&paf_line.query_sequence_name)
+        && target_hodeco_maps.contains_key(&paf_line.query_sequence_name)
+    {
+        ("query", "target", &paf_line.query_sequence_name)
+    } else if !target_hodeco_maps.contains_key(&paf_line.target_sequence_name)
+        && query_hodeco_maps.contains_key(&paf_line.target_sequence_name)
+    {
+        ("target", "query", &paf_line.target_sequence_name)
+    } else {
+        return;
+    };
+
+    let message = format!("{role} sequence has no {role} hodeco map, but a {other_role} hodeco map, the roles of the hodeco maps may be reversed (see --swap-roles)");
+    if run_report.swapped_roles.fetch_add(1, Ordering::Relaxed) == 0 {
+        warn!("The {message}: {sequence_name}. Further such alignments are only counted");
+    }
+    run_report.record_warning(line_number, sequence_name, "swapped-roles", message);
+}
+
+/// Returns the compressed and the decompressed length of an alignment on a sequence,
+/// given its compressed and decompressed start and end coordinates.
+///
+/// Fails if the decompressed alignment is shorter than the compressed one,
+/// which a corrupt hodeco map or a custom coordinate map may cause.
+fn alignment_lengths(
+    role: SequenceRole,
+    sequence_name: &str,
+    compressed_range: (usize, usize),
+    decompressed_range: (usize, usize),
+) -> Result<(usize, usize), HodecoError> {
+    // hodeco_paf_line rejects compressed alignments that do not start before they end.
+    let compressed_length = compressed_range.1 - compressed_range.0;
+    match decompressed_range.1.checked_sub(decompressed_range.0) {
+        Some(decompressed_length) if decompressed_length >= compressed_length => {
+            Ok((compressed_length, decompressed_length))
         }
+        _ => Err(HodecoError::ShrunkAlignment {
+            role,
+            sequence_name: sequence_name.to_string(),
+            compressed_range,
+            decompressed_range,
+        }),
+    }
+}
 
-        hoco_paf.total_number_of_mismatches_and_gaps = Some(total_number_of_mismatches_and_gaps);
-        // assert_eq!(query_hodeco_len, hoco_paf.query_sequence_length);
-        // assert_eq!(target_hodeco_len, hoco_paf.target_sequence_length);
-        info!(
-            "query difference length: {}, query expected length: {}",
-            query_hodeco_len, query_alignment_length
-        );
-        info!(
-            "target difference length: {}, target expected length: {}",
-            target_hodeco_len, target_alignment_length,
+/// Decompresses a PAF line and serialises it.
+/// Returns the output records together with their decompressed query start coordinate,
+/// which is more than one record if the line is split, and none if it is filtered out.
+fn process_paf_line(
+    paf_line: PAFLine,
+    line_number: LineNumber,
+    configuration: &Configuration,
+    context: &RunContext,
+    run_report: &RunReport,
+) -> Result<Vec<(usize, PafRecord)>, HodecoError> {
+    if let Some(map_references) = &run_report.map_references {
+        map_references.count(&paf_line);
+    }
+
+    let is_allowed = |allowlist_names: &Option<HashSet<String>>, sequence_name: &String| {
+        allowlist_names
+            .as_ref()
+            .map(|allowlist_names| allowlist_names.contains(sequence_name))
+            .unwrap_or(true)
+    };
+    if !is_allowed(
+        &context.query_allowlist_names,
+        &paf_line.query_sequence_name,
+    ) || !is_allowed(
+        &context.target_allowlist_names,
+        &paf_line.target_sequence_name,
+    ) {
+        run_report
+            .dropped_by_allowlist
+            .fetch_add(1, Ordering::Relaxed);
+        return Ok(Vec::new());
+    }
+
+    if configuration.target_region_space == CoordinateSpace::Compressed
+        && !is_in_target_regions(&paf_line, &configuration.target_region)
+    {
+        return Ok(Vec::new());
+    }
+
+    check_roles(
+        &paf_line,
+        line_number,
+        &context.query_hodeco_maps,
+        &context.target_hodeco_maps,
+        run_report,
+    );
+
+    if paf_line.cigar_string.is_none() && paf_line.difference_string.is_none() {
+        if configuration.require_alignment_string {
+            return Err(HodecoError::MissingAlignmentString {
+                query_sequence_name: paf_line.query_sequence_name,
+            });
+        }
+        if run_report
+            .without_alignment_string
+            .fetch_add(1, Ordering::Relaxed)
+            == 0
+        {
+            warn!("Alignment of query {} has neither a CIGAR string nor a difference string, its base counts are output in compressed space. Further such alignments are only counted", paf_line.query_sequence_name);
+        }
+        run_report.record_warning(
+            line_number,
+            &paf_line.query_sequence_name,
+            "missing-alignment-string",
+            "alignment has neither a CIGAR string nor a difference string, its base counts are output in compressed space".to_string(),
         );
     }
 
-    if let Some(approximate_per_base_sequence_divergence) =
-        &mut hoco_paf.approximate_per_base_sequence_divergence
+    if let Some(max_expanded_columns) = configuration.max_expanded_columns {
+        let expanded_columns = expanded_difference_string_columns(
+            &paf_line,
+            &context.query_hodeco_maps,
+            &context.target_hodeco_maps,
+        )?;
+        if expanded_columns > max_expanded_columns {
+            return Err(HodecoError::ExpansionTooLarge {
+                query_sequence_name: paf_line.query_sequence_name,
+                expanded_columns,
+                max_expanded_columns,
+            });
+        }
+    }
+
+    let hoco_query_range = (
+        paf_line.query_start_coordinate,
+        paf_line.query_end_coordinate,
+    );
+    let hoco_target_range = (
+        paf_line.target_start_coordinate_on_original_strand,
+        paf_line.target_end_coordinate_on_original_strand,
+    );
+    let hoco_difference_string = if configuration.check_cs_roundtrip {
+        paf_line.difference_string.clone()
+    } else {
+        None
+    };
+    let mut hodeco_paf_line = hodeco_paf_line(
+        paf_line,
+        &context.query_hodeco_maps,
+        &context.target_hodeco_maps,
+    )?;
+    for remap_tag in &configuration.remap_tag {
+        hodeco_coordinate_tag(
+            &mut hodeco_paf_line,
+            &remap_tag.tag,
+            remap_tag.role,
+            &context.query_hodeco_maps,
+            &context.target_hodeco_maps,
+        )?;
+    }
+    let (hoco_query_length, hodeco_query_length) = alignment_lengths(
+        SequenceRole::Query,
+        &hodeco_paf_line.query_sequence_name,
+        hoco_query_range,
+        (
+            hodeco_paf_line.query_start_coordinate,
+            hodeco_paf_line.query_end_coordinate,
+        ),
+    )?;
+    let (hoco_target_length, hodeco_target_length) = alignment_lengths(
+        SequenceRole::Target,
+        &hodeco_paf_line.target_sequence_name,
+        hoco_target_range,
+        (
+            hodeco_paf_line.target_start_coordinate_on_original_strand,
+            hodeco_paf_line.target_end_coordinate_on_original_strand,
+        ),
+    )?;
+    let annotation = if configuration.annotate_decompression {
+        let query_growth = hodeco_query_length - hoco_query_length;
+        let target_growth = hodeco_target_length - hoco_target_length;
+        vec![
+            "hd:i:1".to_string(),
+            format!("qx:i:{query_growth}"),
+            format!("tx:i:{target_growth}"),
+        ]
+    } else {
+        Vec::new()
+    };
+    if configuration.check_cs_consistency {
+        check_difference_string_consistency(&hodeco_paf_line)?;
+    }
+    if configuration.check_cs_roundtrip {
+        check_difference_string_roundtrip(
+            hoco_difference_string.as_ref(),
+            &hodeco_paf_line,
+            &context.query_hodeco_maps,
+            &context.target_hodeco_maps,
+        )?;
+    }
+    check_divergence(
+        &mut hodeco_paf_line,
+        configuration.clamp_divergence,
+        line_number,
+        run_report,
+    );
+    if configuration.target_region_space == CoordinateSpace::Decompressed
+        && !is_in_target_regions(&hodeco_paf_line, &configuration.target_region)
     {
-        *approximate_per_base_sequence_divergence *=
-            hoco_paf.query_sequence_length as f64 / hoco_query_sequence_length as f64;
+        return Ok(Vec::new());
     }
-    if let Some(gap_compressed_per_base_sequence_divergence) =
-        &mut hoco_paf.gap_compressed_per_base_sequence_divergence
+
+    let target_offset = context.target_offsets.as_ref().and_then(|target_offsets| {
+        let target_sequence_name = &hodeco_paf_line.target_sequence_name;
+        let target_offset = target_offsets.get(target_sequence_name);
+        if target_offset.is_none() {
+            let message = "target has no entry in the target offset table, its coordinates are not offset";
+            if run_report
+                .without_target_offset
+                .fetch_add(1, Ordering::Relaxed)
+                == 0
+            {
+                warn!("The {message}: {target_sequence_name}. Further such alignments are only counted");
+            }
+            run_report.record_warning(
+                line_number,
+                target_sequence_name,
+                "missing-target-offset",
+                message.to_string(),
+            );
+        }
+        target_offset
+    });
+
+    if let Some(TargetOffset {
+        offset,
+        name,
+        length: Some(length),
+    }) = target_offset
     {
-        *gap_compressed_per_base_sequence_divergence *=
-            hoco_paf.query_sequence_length as f64 / hoco_query_sequence_length as f64;
+        let offset_target_end = hodeco_paf_line.target_end_coordinate_on_original_strand + offset;
+        if offset_target_end > *length {
+            return Err(HodecoError::CoordinateOutOfBounds {
+                role: SequenceRole::Target,
+                sequence_name: name.clone().unwrap_or(hodeco_paf_line.target_sequence_name),
+                coordinate: offset_target_end,
+                decompressed_length: *length,
+            });
+        }
+    }
+
+    let hodeco_paf_lines = if let Some(max_gap_length) = configuration.split_on_gap {
+        split_on_gaps(hodeco_paf_line, max_gap_length)
+    } else {
+        vec![hodeco_paf_line]
+    };
+    run_report.statistics.add(
+        hodeco_paf_lines.len(),
+        [hoco_query_length, hodeco_query_length],
+        [hoco_target_length, hodeco_target_length],
+    );
+    if configuration.count_only {
+        return Ok(Vec::new());
+    }
+
+    let mut result = Vec::new();
+    for mut hodeco_paf_line in hodeco_paf_lines {
+        let query_start_coordinate = hodeco_paf_line.query_start_coordinate;
+        if let Some(target_offset) = target_offset {
+            hodeco_paf_line.target_start_coordinate_on_original_strand += target_offset.offset;
+            hodeco_paf_line.target_end_coordinate_on_original_strand += target_offset.offset;
+            if let Some(name) = &target_offset.name {
+                hodeco_paf_line.target_sequence_name = name.clone();
+            }
+            hodeco_paf_line.target_sequence_length = target_offset
+                .length
+                .unwrap_or(hodeco_paf_line.target_sequence_length + target_offset.offset);
+        }
+        convert_output_coordinates(&mut hodeco_paf_line, configuration);
+        if !configuration.keep_tags.is_empty() || !configuration.drop_tags.is_empty() {
+            filter_tags(&mut hodeco_paf_line, configuration);
+        }
+        // Unknown fields are serialised after all other optional fields.
+        hodeco_paf_line
+            .unknown_fields
+            .extend(annotation.iter().cloned());
+
+        result.push((
+            query_start_coordinate,
+            paf_record(&hodeco_paf_line, configuration),
+        ));
+    }
+    Ok(result)
+}
+
+/// Warns if the divergence values of a PAF line are outside of [0, 1], and clamps them if requested.
+fn check_divergence(
+    paf_line: &mut PAFLine,
+    clamp_divergence: bool,
+    line_number: LineNumber,
+    run_report: &RunReport,
+) {
+    for (tag, divergence) in [
+        ("dv", &mut paf_line.approximate_per_base_sequence_divergence),
+        (
+            "de",
+            &mut paf_line.gap_compressed_per_base_sequence_divergence,
+        ),
+    ] {
+        if let Some(divergence) = divergence {
+            if !(0.0..=1.0).contains(divergence) {
+                let message = format!(
+                    "Divergence {tag}:f:{divergence} of alignment between query {} and target {} is outside of [0, 1]{}",
+                    paf_line.query_sequence_name,
+                    paf_line.target_sequence_name,
+                    if clamp_divergence { ", clamping" } else { "" }
+                );
+                warn!("{message}");
+                run_report.record_warning(
+                    line_number,
+                    &paf_line.query_sequence_name,
+                    "divergence-out-of-range",
+                    message,
+                );
+
+                if clamp_divergence {
+                    *divergence = divergence.clamp(0.0, 1.0);
+                }
+            }
+        }
+    }
+}
+
+/// Removes the optional tags from a PAF line that are not selected by `--keep-tags` and `--drop-tags`.
+fn filter_tags(paf_line: &mut PAFLine, configuration: &Configuration) {
+    let is_kept = |tag: &str| {
+        if configuration.keep_tags.is_empty() {
+            !configuration.drop_tags.iter().any(|dropped| dropped == tag)
+        } else {
+            configuration.keep_tags.iter().any(|kept| kept == tag)
+        }
+    };
+
+    fn filter<T>(field: &mut Option<T>, is_kept: bool) {
+        if !is_kept {
+            *field = None;
+        }
+    }
+
+    filter(&mut paf_line.alignment_type, is_kept("tp"));
+    filter(&mut paf_line.number_of_minimisers, is_kept("cm"));
+    filter(&mut paf_line.chaining_score, is_kept("s1"));
+    filter(&mut paf_line.best_secondary_chaining_score, is_kept("s2"));
+    filter(
+        &mut paf_line.total_number_of_mismatches_and_gaps,
+        is_kept("NM"),
+    );
+    filter(&mut paf_line.unknown_md, is_kept("MD"));
+    filter(&mut paf_line.dp_alignment_score, is_kept("AS"));
+    filter(&mut paf_line.supplementary_alignments, is_kept("SA"));
+    filter(&mut paf_line.best_segment_dp_score, is_kept("ms"));
+    filter(&mut paf_line.number_of_ambiguous_bases, is_kept("nn"));
+    filter(&mut paf_line.transcript_strand, is_kept("ts"));
+    filter(&mut paf_line.cigar_string, is_kept("cg"));
+    filter(&mut paf_line.difference_string, is_kept("cs"));
+    filter(
+        &mut paf_line.approximate_per_base_sequence_divergence,
+        is_kept("dv"),
+    );
+    filter(
+        &mut paf_line.gap_compressed_per_base_sequence_divergence,
+        is_kept("de"),
+    );
+    filter(
+        &mut paf_line.length_of_query_regions_with_repetitive_seeds,
+        is_kept("rl"),
+    );
+    // Unknown fields are stored with their header, e.g. `zd:i:5`.
+    paf_line
+        .unknown_fields
+        .retain(|unknown_field| is_kept(unknown_field.split(':').next().unwrap()));
+}
+
+/// Converts the coordinates of a PAF line from zero-based half-open intervals
+/// into the base and interval convention selected for the output.
+fn convert_output_coordinates(paf_line: &mut PAFLine, configuration: &Configuration) {
+    let base_offset = match configuration.coordinate_output_base {
+        CoordinateBase::Zero => 0,
+        CoordinateBase::One => 1,
+    };
+    let closed_offset = match configuration.coordinate_output_interval {
+        IntervalConvention::HalfOpen => 0,
+        IntervalConvention::Closed => 1,
+    };
+
+    // Ends are always greater than starts, so they do not underflow for closed intervals.
+    paf_line.query_start_coordinate += base_offset;
+    paf_line.query_end_coordinate = paf_line.query_end_coordinate + base_offset - closed_offset;
+    paf_line.target_start_coordinate_on_original_strand += base_offset;
+    paf_line.target_end_coordinate_on_original_strand =
+        paf_line.target_end_coordinate_on_original_strand + base_offset - closed_offset;
+}
+
+/// Converts a PAF line into an output record according to the output options.
+fn paf_record(paf_line: &PAFLine, configuration: &Configuration) -> PafRecord {
+    PafRecord::from_paf_line(
+        paf_line,
+        configuration.strand_format.format(paf_line.strand),
+    )
+}
+
+/// The output produced for a line of the input PAF file.
+enum OutputItem {
+    /// A comment line that is passed through.
+    Comment(String),
+    /// The decompressed records of a block of alignments,
+    /// with the original line if `--emit-original` is given, in which case the block contains a single alignment.
+    Records {
+        records: Vec<PafRecord>,
+        original: Option<String>,
+    },
+}
+
+/// The output file, which may be BGZF compressed.
+enum OutputFile {
+    Plain(File),
+    Bgzf(bgzf::Writer<File>),
+    /// No output file was given.
+    Discarded(io::Sink),
+}
+
+impl OutputFile {
+    /// Ends the output, which writes the end-of-file marker block of BGZF.
+    /// The BGZF writer would do this when dropped as well, but ignore errors.
+    fn finish(self) -> io::Result<()> {
+        match self {
+            OutputFile::Bgzf(writer) => writer.finish().map(drop),
+            OutputFile::Plain(_) | OutputFile::Discarded(_) => Ok(()),
+        }
+    }
+}
+
+impl Write for OutputFile {
+    fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+        match self {
+            OutputFile::Plain(file) => file.write(buffer),
+            OutputFile::Bgzf(writer) => writer.write(buffer),
+            OutputFile::Discarded(sink) => sink.write(buffer),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            OutputFile::Plain(file) => file.flush(),
+            OutputFile::Bgzf(writer) => writer.flush(),
+            OutputFile::Discarded(sink) => sink.flush(),
+        }
+    }
+}
+
+/// The writers of the output file and the original output file.
+struct OutputWriters {
+    output_file_writer: BufWriter<OutputFile>,
+    original_output_file_writer: Option<BufWriter<File>>,
+    output_format: OutputFormat,
+    line_buffered: bool,
+}
+
+impl OutputWriters {
+    fn new(
+        output_file: OutputFile,
+        original_output_file: Option<File>,
+        configuration: &Configuration,
+    ) -> Self {
+        Self {
+            output_file_writer: BufWriter::with_capacity(configuration.io_buffer_size, output_file),
+            original_output_file_writer: original_output_file.map(|original_output_file| {
+                BufWriter::with_capacity(configuration.io_buffer_size, original_output_file)
+            }),
+            output_format: configuration.output_format,
+            line_buffered: configuration.line_buffered,
+        }
+    }
+
+    fn write(&mut self, output_item: OutputItem) {
+        match output_item {
+            OutputItem::Comment(comment) => {
+                write_paf_line(&mut self.output_file_writer, &comment);
+                if let Some(original_output_file_writer) = &mut self.original_output_file_writer {
+                    write_paf_line(original_output_file_writer, &comment);
+                }
+            }
+            OutputItem::Records {
+                records,
+                original: None,
+            } => {
+                for record in records {
+                    self.write_record(&record);
+                }
+            }
+            OutputItem::Records {
+                records,
+                original: Some(original),
+            } => {
+                if let Some(original_output_file_writer) = &mut self.original_output_file_writer {
+                    for record in records {
+                        write_paf_line(&mut self.output_file_writer, &record.to_string());
+                        write_paf_line(original_output_file_writer, &original);
+                    }
+                } else {
+                    write_paf_line(&mut self.output_file_writer, &format!("C\t{original}"));
+                    for record in records {
+                        write_paf_line(&mut self.output_file_writer, &format!("D\t{record}"));
+                    }
+                }
+            }
+        }
+
+        if self.line_buffered {
+            self.flush();
+        }
+    }
+
+    /// Writes a decompressed record into the output file in the output format.
+    fn write_record(&mut self, record: &PafRecord) {
+        match self.output_format {
+            OutputFormat::Text => write_paf_line(&mut self.output_file_writer, &record.to_string()),
+            OutputFormat::Cbor => {
+                let record = record.to_cbor();
+                self.output_file_writer
+                    .write_all(&record)
+                    .unwrap_or_else(|error| {
+                        exit_with(
+                            ExitCode::IoError,
+                            format!("Cannot write CBOR record: {error:?}"),
+                        )
+                    });
+            }
+        }
+    }
+
+    fn flush(&mut self) {
+        flush_output(&mut self.output_file_writer);
+        if let Some(original_output_file_writer) = &mut self.original_output_file_writer {
+            flush_output(original_output_file_writer);
+        }
+    }
+
+    /// Flushes and ends the output files.
+    fn finish(mut self) {
+        self.flush();
+        let output_file = self
+            .output_file_writer
+            .into_inner()
+            .unwrap_or_else(|error| {
+                exit_with(
+                    ExitCode::IoError,
+                    format!("Cannot flush output: {:?}", error.error()),
+                )
+            });
+        output_file.finish().unwrap_or_else(|error| {
+            exit_with(
+                ExitCode::IoError,
+                format!("Cannot finish output: {error:?}"),
+            )
+        });
     }
+}
+
+fn write_paf_line(output_file_writer: &mut impl Write, hodeco_paf_line: &str) {
+    output_file_writer
+        .write_all(hodeco_paf_line.as_bytes())
+        .unwrap_or_else(|error| {
+            exit_with(
+                ExitCode::IoError,
+                format!("Cannot write PAF line: {error:?}"),
+            )
+        });
+    output_file_writer.write_all(b"\n").unwrap_or_else(|error| {
+        exit_with(
+            ExitCode::IoError,
+            format!("Cannot write line feed: {error:?}"),
+        )
+    });
+}
 
-    hoco_paf
+/// Flushes the output explicitly, since errors when flushing on drop are ignored.
+fn flush_output(output_file_writer: &mut impl Write) {
+    output_file_writer.flush().unwrap_or_else(|error| {
+        exit_with(ExitCode::IoError, format!("Cannot flush output: {error:?}"))
+    });
 }
 
-fn homopolymer_decompress_string(input: &str, hodeco_map: &[usize]) -> String {
-    let mut result = String::new();
-    for (index, character) in input.chars().enumerate() {
-        let count = hodeco_map[index + 1] - hodeco_map[index];
-        for _ in 0..count {
-            result.push(character);
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trailing_unparsed_content_is_an_error() {
+        let line = "q2\t6\t1\t5\t-\tt2\t8\t2\t6\t4\t4\t30\tcg:Z:4M";
+        assert!(parse_paf_line(line).is_ok());
+        assert_eq!(
+            parse_paf_line(&format!("{line}\ntrailing\tcontent")).unwrap_err(),
+            "line was not parsed completely, unparsed remainder: \"trailing\\tcontent\""
+        );
+    }
+
+    #[test]
+    fn maps_exceeding_the_memory_budget_keep_their_smallest_representation() {
+        let smallest = |offsets: Vec<usize>| {
+            HodecoMap::from_offsets(offsets, MapOffsetWidth::Auto)
+                .unwrap()
+                .into_representation(MapRepresentation::Smallest)
+        };
+        // A mostly incompressible sequence, which is smallest when run-length encoded.
+        let query_hodeco_maps: HashMap<_, _> =
+            [("q".to_string(), smallest((0..100).chain([102]).collect()))].into();
+        // A highly compressed sequence, which is smallest when stored densely.
+        let target_hodeco_maps: HashMap<_, _> = [(
+            "t".to_string(),
+            smallest((0..20).map(|offset| offset * 3).collect()),
+        )]
+        .into();
+        assert!(matches!(query_hodeco_maps["q"], HodecoMap::RunLength(_)));
+        assert!(matches!(target_hodeco_maps["t"], HodecoMap::Narrow(_)));
+
+        assert_eq!(
+            fit_hodeco_maps_into_memory(
+                query_hodeco_maps.clone(),
+                target_hodeco_maps.clone(),
+                1,
+                MapOffsetWidth::Auto,
+            ),
+            (query_hodeco_maps.clone(), target_hodeco_maps.clone())
+        );
+        // With enough memory, the run-length encoded map is stored densely.
+        let (query_hodeco_maps, _) = fit_hodeco_maps_into_memory(
+            query_hodeco_maps,
+            target_hodeco_maps,
+            usize::MAX / 2,
+            MapOffsetWidth::Auto,
+        );
+        assert!(matches!(query_hodeco_maps["q"], HodecoMap::Narrow(_)));
+    }
+
+    #[test]
+    fn shrunk_alignments_are_an_error() {
+        assert_eq!(
+            alignment_lengths(SequenceRole::Query, "q", (1, 3), (2, 6)),
+            Ok((2, 4))
+        );
+        for decompressed_range in [(2, 3), (6, 2)] {
+            assert_eq!(
+                alignment_lengths(SequenceRole::Target, "t", (1, 3), decompressed_range),
+                Err(HodecoError::ShrunkAlignment {
+                    role: SequenceRole::Target,
+                    sequence_name: "t".to_string(),
+                    compressed_range: (1, 3),
+                    decompressed_range,
+                })
+            );
         }
     }
-    result
 }
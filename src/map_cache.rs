@@ -0,0 +1,72 @@
+use crate::map_format::{MapFormat, MapWriter};
+use std::collections::hash_map::DefaultHasher;
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// A cached copy of a hodeco map file in the packed format, which is much faster to load than CBOR.
+///
+/// The cache consists of the packed map file and a stamp file describing the source file it was created from.
+/// The cache is only valid if the stamp matches the current path, size and modification time of the source file.
+pub struct MapCache {
+    packed_path: PathBuf,
+    stamp_path: PathBuf,
+    stamp: String,
+}
+
+impl MapCache {
+    /// Locates the cache of the given source file in the given cache directory.
+    pub fn new(cache_directory: &Path, source_path: &Path) -> io::Result<Self> {
+        let source_path = source_path.canonicalize()?;
+        let metadata = fs::metadata(&source_path)?;
+        let modified = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let stamp = format!(
+            "{}\t{}\t{}.{:09}\n",
+            source_path.display(),
+            metadata.len(),
+            modified.as_secs(),
+            modified.subsec_nanos()
+        );
+
+        let mut hasher = DefaultHasher::new();
+        source_path.hash(&mut hasher);
+        let file_stem = format!("{:016x}", hasher.finish());
+        Ok(Self {
+            packed_path: cache_directory.join(format!("{file_stem}.packed")),
+            stamp_path: cache_directory.join(format!("{file_stem}.stamp")),
+            stamp,
+        })
+    }
+
+    /// Returns true if the cache exists and was created from the current version of the source file.
+    pub fn is_valid(&self) -> bool {
+        self.packed_path.is_file()
+            && fs::read_to_string(&self.stamp_path).ok().as_deref() == Some(self.stamp.as_str())
+    }
+
+    /// Opens the cached map file for reading.
+    pub fn open(&self) -> io::Result<File> {
+        File::open(&self.packed_path)
+    }
+
+    /// Starts rewriting the cache.
+    /// The cache is invalid until [MapCache::commit] is called.
+    pub fn create(&self) -> io::Result<MapWriter<File>> {
+        match fs::remove_file(&self.stamp_path) {
+            Err(error) if error.kind() != io::ErrorKind::NotFound => return Err(error),
+            _ => {}
+        }
+        MapWriter::new(File::create(&self.packed_path)?, MapFormat::Packed)
+    }
+
+    /// Marks the rewritten cache as valid.
+    /// The map writer returned by [MapCache::create] must be flushed before.
+    pub fn commit(&self) -> io::Result<()> {
+        fs::write(&self.stamp_path, &self.stamp)
+    }
+}
@@ -0,0 +1,728 @@
+use clap::ValueEnum;
+use serde::de::{
+    DeserializeSeed, Deserializer, Error as _, IgnoredAny, MapAccess, SeqAccess, Visitor,
+};
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+
+/// The file format of hodeco maps.
+#[derive(ValueEnum, Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MapFormat {
+    /// A sequence of CBOR items `(sequence_name, MapEntry { compressed_len, offsets })`,
+    /// where `compressed_len` is the compressed length of the sequence and `MapEntry` is a CBOR map.
+    /// Items of the older form `(sequence_name, offsets)` are accepted as well.
+    Cbor,
+    /// One line per sequence, containing the sequence name and the comma-separated offsets separated by a tab.
+    Tsv,
+    /// A binary format that is much faster to read than CBOR.
+    /// It starts with the magic bytes `HODECOPK`, followed by one record per sequence.
+    /// Each record consists of the length of the sequence name, the sequence name in UTF-8,
+    /// the number of offsets and the offsets, with all integers stored as 64 bit little endian.
+    Packed,
+    /// One JSON object `{"sequence_name": ..., "offsets": [...]}` per line.
+    Json,
+    /// A binary format like the packed format, but much smaller.
+    /// It starts with the magic bytes `HODECODL`, followed by records with the same fields as in the packed format.
+    /// Instead of the offsets, the records store the difference of each offset to the previous one,
+    /// or to zero for the first offset, and all integers are stored as unsigned LEB128.
+    /// Most homopolymer runs are short, so most differences take a single byte.
+    Delta,
+}
+
+/// The magic bytes at the start of a file in the packed format.
+const PACKED_MAGIC: &[u8; 8] = b"HODECOPK";
+
+/// The magic bytes at the start of a file in the delta format.
+const DELTA_MAGIC: &[u8; 8] = b"HODECODL";
+
+/// An error that occurred while reading a hodeco map file.
+#[derive(Debug)]
+pub enum MapReadError {
+    /// The file could not be read.
+    Io(io::Error),
+    /// The file ends in the middle of an entry.
+    Truncated(String),
+    /// An entry is malformed.
+    Malformed(String),
+}
+
+impl Display for MapReadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MapReadError::Io(error) => write!(f, "{error}"),
+            MapReadError::Truncated(message) => write!(f, "truncated entry: {message}"),
+            MapReadError::Malformed(message) => write!(f, "malformed entry: {message}"),
+        }
+    }
+}
+
+/// An iterator over the entries `(sequence_name, offsets)` of a hodeco map file.
+pub type MapEntries<'reader> =
+    Box<dyn Iterator<Item = Result<(String, Vec<usize>), MapReadError>> + 'reader>;
+
+/// Returns an iterator over the entries of a hodeco map file in the given format.
+///
+/// Entries without offsets are rejected as malformed in all formats,
+/// since each map contains at least the offset of the start of the sequence.
+/// Entries whose offsets are not strictly increasing are rejected as well,
+/// since every compressed character expands into at least one decompressed character.
+pub fn read_map_entries<'reader>(
+    reader: impl Read + 'reader,
+    format: MapFormat,
+) -> MapEntries<'reader> {
+    let entries: MapEntries<'reader> = match format {
+        MapFormat::Cbor => Box::new(CborEntries {
+            reader: BufReader::new(reader),
+        }),
+        MapFormat::Tsv => Box::new(TsvEntries {
+            reader: BufReader::new(reader),
+            line: String::new(),
+        }),
+        MapFormat::Json => Box::new(JsonEntries {
+            reader: BufReader::new(reader),
+            line: String::new(),
+        }),
+        MapFormat::Packed | MapFormat::Delta => Box::new(BinaryEntries {
+            reader: BufReader::new(reader),
+            format,
+            magic_checked: false,
+        }),
+    };
+    Box::new(entries.map(|entry| {
+        entry.and_then(|(sequence_name, offsets)| {
+            if offsets.is_empty() {
+                Err(MapReadError::Malformed(format!(
+                    "sequence {sequence_name} has no offsets"
+                )))
+            } else if let Some(position) = offsets.windows(2).position(|run| run[0] >= run[1]) {
+                Err(MapReadError::Malformed(format!(
+                    "sequence {sequence_name} has an empty or negative run at compressed position {position}: offsets {} and {}",
+                    offsets[position],
+                    offsets[position + 1]
+                )))
+            } else {
+                Ok((sequence_name, offsets))
+            }
+        })
+    }))
+}
+
+/// The extended entry of a hodeco map file in the CBOR format.
+///
+/// It records the compressed length of the sequence explicitly,
+/// such that a map that does not match its sequence is detected when it is loaded.
+#[derive(Serialize)]
+struct MapEntry<'offsets> {
+    compressed_len: usize,
+    offsets: &'offsets [usize],
+}
+
+struct CborEntries<R> {
+    reader: BufReader<R>,
+}
+
+/// The maximum number of offsets that are preallocated according to the length of a CBOR array,
+/// such that a corrupt length does not allocate an arbitrary amount of memory.
+const MAX_PREALLOCATED_OFFSETS: usize = 1 << 24;
+
+/// A CBOR item of a hodeco map file, i.e. an entry `(sequence_name, offsets)`.
+/// Accepts both the extended and the older form of the item.
+///
+/// The item is deserialized with a visitor instead of a [ciborium::value::Value],
+/// since a value tree stores each offset as a separate node several times larger than the offset itself.
+struct CborItem {
+    sequence_name: String,
+    offsets: Vec<usize>,
+}
+
+impl<'de> Deserialize<'de> for CborItem {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_tuple(2, CborItemVisitor)
+    }
+}
+
+struct CborItemVisitor;
+
+impl<'de> Visitor<'de> for CborItemVisitor {
+    type Value = CborItem;
+
+    fn expecting(&self, formatter: &mut Formatter) -> std::fmt::Result {
+        write!(formatter, "a pair of sequence name and map")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut item: A) -> Result<Self::Value, A::Error> {
+        let sequence_name: String = item
+            .next_element()?
+            .ok_or_else(|| A::Error::invalid_length(0, &self))?;
+        let offsets = item
+            .next_element_seed(CborMapSeed {
+                sequence_name: &sequence_name,
+            })?
+            .ok_or_else(|| A::Error::invalid_length(1, &self))?;
+        if item.next_element::<IgnoredAny>()?.is_some() {
+            return Err(A::Error::invalid_length(3, &self));
+        }
+        Ok(CborItem {
+            sequence_name,
+            offsets,
+        })
+    }
+}
+
+/// Deserializes the map of a CBOR item into its offsets,
+/// from either a list of offsets or a [MapEntry].
+struct CborMapSeed<'name> {
+    sequence_name: &'name str,
+}
+
+impl<'de> DeserializeSeed<'de> for CborMapSeed<'_> {
+    type Value = Vec<usize>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for CborMapSeed<'_> {
+    type Value = Vec<usize>;
+
+    fn expecting(&self, formatter: &mut Formatter) -> std::fmt::Result {
+        write!(
+            formatter,
+            "a list of offsets or a map entry for sequence {}",
+            self.sequence_name
+        )
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut offsets: A) -> Result<Self::Value, A::Error> {
+        let mut result = Vec::with_capacity(
+            offsets
+                .size_hint()
+                .unwrap_or(0)
+                .min(MAX_PREALLOCATED_OFFSETS),
+        );
+        while let Some(offset) = offsets.next_element()? {
+            result.push(offset);
+        }
+        Ok(result)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut entry: A) -> Result<Self::Value, A::Error> {
+        let sequence_name = self.sequence_name;
+        let mut compressed_len: Option<usize> = None;
+        let mut offsets: Option<Vec<usize>> = None;
+        while let Some(key) = entry.next_key::<String>()? {
+            match key.as_str() {
+                "compressed_len" => compressed_len = Some(entry.next_value()?),
+                "offsets" => offsets = Some(entry.next_value()?),
+                _ => {
+                    entry.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        let (compressed_len, offsets) =
+            if let (Some(compressed_len), Some(offsets)) = (compressed_len, offsets) {
+                (compressed_len, offsets)
+            } else {
+                return Err(A::Error::custom(format!(
+                    "map of sequence {sequence_name} lacks a compressed_len or offsets field"
+                )));
+            };
+        if offsets.len().checked_sub(1) != Some(compressed_len) {
+            return Err(A::Error::custom(format!(
+                "map of sequence {sequence_name} has compressed length {compressed_len}, but {} offsets",
+                offsets.len(),
+            )));
+        }
+        Ok(offsets)
+    }
+}
+
+impl<R: Read> Iterator for CborEntries<R> {
+    type Item = Result<(String, Vec<usize>), MapReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // The file may only end between items.
+        match self.reader.fill_buf() {
+            Ok([]) => return None,
+            Ok(_) => {}
+            Err(error) => return Some(Err(MapReadError::Io(error))),
+        }
+
+        Some(
+            ciborium::de::from_reader(&mut self.reader)
+                .map(|item: CborItem| (item.sequence_name, item.offsets))
+                .map_err(|error| match error {
+                    ciborium::de::Error::Io(error)
+                        if error.kind() == io::ErrorKind::UnexpectedEof =>
+                    {
+                        MapReadError::Truncated(format!("{error:?}"))
+                    }
+                    ciborium::de::Error::Io(error) => MapReadError::Io(error),
+                    ciborium::de::Error::Semantic(_, message) => MapReadError::Malformed(message),
+                    error => MapReadError::Malformed(format!("{error:?}")),
+                }),
+        )
+    }
+}
+
+struct TsvEntries<R> {
+    reader: BufReader<R>,
+    line: String,
+}
+
+impl<R: Read> Iterator for TsvEntries<R> {
+    type Item = Result<(String, Vec<usize>), MapReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.line.clear();
+        match self.reader.read_line(&mut self.line) {
+            Ok(0) => return None,
+            Ok(_) => {}
+            Err(error) => return Some(Err(MapReadError::Io(error))),
+        }
+
+        let line = if let Some(line) = self.line.strip_suffix('\n') {
+            line
+        } else {
+            return Some(Err(MapReadError::Truncated(format!(
+                "last line does not end with a line feed: {}",
+                self.line
+            ))));
+        };
+        let (sequence_name, offsets) = if let Some(split) = line.split_once('\t') {
+            split
+        } else {
+            return Some(Err(MapReadError::Malformed(format!(
+                "missing tab in line: {line}"
+            ))));
+        };
+        let offsets = if offsets.is_empty() {
+            Ok(Vec::new())
+        } else {
+            offsets
+                .split(',')
+                .map(|offset| {
+                    offset.parse().map_err(|error| {
+                        MapReadError::Malformed(format!(
+                            "cannot parse offset {offset:?} of sequence {sequence_name}: {error}"
+                        ))
+                    })
+                })
+                .collect()
+        };
+        Some(offsets.map(|offsets| (sequence_name.to_string(), offsets)))
+    }
+}
+
+/// An entry of a hodeco map file in the JSON format.
+#[derive(Deserialize)]
+struct JsonEntry {
+    sequence_name: String,
+    offsets: Vec<usize>,
+}
+
+/// Like [JsonEntry], but borrowing its fields for writing.
+#[derive(Serialize)]
+struct JsonEntryRef<'entry> {
+    sequence_name: &'entry str,
+    offsets: &'entry [usize],
+}
+
+struct JsonEntries<R> {
+    reader: BufReader<R>,
+    line: String,
+}
+
+impl<R: Read> Iterator for JsonEntries<R> {
+    type Item = Result<(String, Vec<usize>), MapReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.line.clear();
+        match self.reader.read_line(&mut self.line) {
+            Ok(0) => return None,
+            Ok(_) => {}
+            Err(error) => return Some(Err(MapReadError::Io(error))),
+        }
+
+        let line = if let Some(line) = self.line.strip_suffix('\n') {
+            line
+        } else {
+            return Some(Err(MapReadError::Truncated(format!(
+                "last line does not end with a line feed: {}",
+                self.line
+            ))));
+        };
+        Some(
+            serde_json::from_str(line)
+                .map(|entry: JsonEntry| (entry.sequence_name, entry.offsets))
+                .map_err(|error| {
+                    MapReadError::Malformed(format!("cannot parse line {line:?}: {error}"))
+                }),
+        )
+    }
+}
+
+/// Reads the entries of the binary formats, i.e. [MapFormat::Packed] and [MapFormat::Delta].
+struct BinaryEntries<R> {
+    reader: BufReader<R>,
+    format: MapFormat,
+    magic_checked: bool,
+}
+
+impl<R: Read> BinaryEntries<R> {
+    /// Reads an integer that is part of a record.
+    fn read_record_u64(&mut self) -> Result<u64, MapReadError> {
+        let truncated = |error: io::Error| {
+            if error.kind() == io::ErrorKind::UnexpectedEof {
+                MapReadError::Truncated("file ends within a record".to_string())
+            } else {
+                MapReadError::Io(error)
+            }
+        };
+        if self.format != MapFormat::Delta {
+            let mut bytes = [0; 8];
+            self.reader.read_exact(&mut bytes).map_err(truncated)?;
+            return Ok(u64::from_le_bytes(bytes));
+        }
+
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let mut byte = [0];
+            self.reader.read_exact(&mut byte).map_err(truncated)?;
+            let bits = u64::from(byte[0] & 0x7f);
+            if bits << shift >> shift != bits {
+                break;
+            }
+            value |= bits << shift;
+            if byte[0] & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(MapReadError::Malformed(
+            "integer does not fit into 64 bits".to_string(),
+        ))
+    }
+
+    fn read_entry(&mut self) -> Result<Option<(String, Vec<usize>)>, MapReadError> {
+        if !self.magic_checked {
+            let (expected_magic, format_name) = match self.format {
+                MapFormat::Delta => (DELTA_MAGIC, "delta"),
+                _ => (PACKED_MAGIC, "packed"),
+            };
+            let mut magic = [0; 8];
+            self.reader
+                .read_exact(&mut magic)
+                .map_err(MapReadError::Io)?;
+            if &magic != expected_magic {
+                return Err(MapReadError::Malformed(format!(
+                    "file does not start with the magic bytes of the {format_name} format"
+                )));
+            }
+            self.magic_checked = true;
+        }
+
+        // The file may only end at the start of a record.
+        if self.reader.fill_buf().map_err(MapReadError::Io)?.is_empty() {
+            return Ok(None);
+        }
+        let name_length = self.read_record_u64()?;
+        // Read through `take` to not allocate huge buffers for corrupt lengths.
+        let mut name = Vec::new();
+        (&mut self.reader)
+            .take(name_length)
+            .read_to_end(&mut name)
+            .map_err(MapReadError::Io)?;
+        if name.len() as u64 != name_length {
+            return Err(MapReadError::Truncated(
+                "file ends within a sequence name".to_string(),
+            ));
+        }
+        let name = String::from_utf8(name).map_err(|error| {
+            MapReadError::Malformed(format!("sequence name is not valid UTF-8: {error}"))
+        })?;
+
+        let offset_count = self.read_record_u64()? as usize;
+        let mut offsets = Vec::with_capacity(offset_count.min(1 << 20));
+        let mut previous_offset = 0usize;
+        for _ in 0..offset_count {
+            let integer = self.read_record_u64()? as usize;
+            let offset = if self.format == MapFormat::Delta {
+                previous_offset.checked_add(integer).ok_or_else(|| {
+                    MapReadError::Malformed(format!(
+                        "offsets of sequence {name} do not fit into {} bits",
+                        usize::BITS
+                    ))
+                })?
+            } else {
+                integer
+            };
+            offsets.push(offset);
+            previous_offset = offset;
+        }
+        Ok(Some((name, offsets)))
+    }
+}
+
+impl<R: Read> Iterator for BinaryEntries<R> {
+    type Item = Result<(String, Vec<usize>), MapReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_entry().transpose()
+    }
+}
+
+/// Writes the entries of a hodeco map file in a given format.
+pub enum MapWriter<W: Write> {
+    /// Writes CBOR.
+    Cbor(BufWriter<W>),
+    /// Writes TSV.
+    Tsv(BufWriter<W>),
+    /// Writes the packed format.
+    Packed(BufWriter<W>),
+    /// Writes JSON.
+    Json(BufWriter<W>),
+    /// Writes the delta format.
+    Delta(BufWriter<W>),
+}
+
+impl<W: Write> MapWriter<W> {
+    /// Creates a writer for the given format.
+    pub fn new(writer: W, format: MapFormat) -> io::Result<Self> {
+        Ok(match format {
+            MapFormat::Cbor => Self::Cbor(BufWriter::new(writer)),
+            MapFormat::Tsv => Self::Tsv(BufWriter::new(writer)),
+            MapFormat::Packed => {
+                let mut writer = BufWriter::new(writer);
+                writer.write_all(PACKED_MAGIC)?;
+                Self::Packed(writer)
+            }
+            MapFormat::Json => Self::Json(BufWriter::new(writer)),
+            MapFormat::Delta => {
+                let mut writer = BufWriter::new(writer);
+                writer.write_all(DELTA_MAGIC)?;
+                Self::Delta(writer)
+            }
+        })
+    }
+
+    /// Writes a single entry.
+    ///
+    /// In the delta format, the offsets must not decrease.
+    pub fn write_entry(&mut self, sequence_name: &str, offsets: &[usize]) -> io::Result<()> {
+        match self {
+            Self::Cbor(writer) => ciborium::ser::into_writer(
+                &(
+                    sequence_name,
+                    MapEntry {
+                        compressed_len: offsets.len().saturating_sub(1),
+                        offsets,
+                    },
+                ),
+                writer,
+            )
+            .map_err(cbor_to_io_error),
+            Self::Tsv(writer) => {
+                write!(writer, "{sequence_name}\t")?;
+                for (index, offset) in offsets.iter().enumerate() {
+                    if index > 0 {
+                        write!(writer, ",")?;
+                    }
+                    write!(writer, "{offset}")?;
+                }
+                writeln!(writer)
+            }
+            Self::Packed(writer) => {
+                writer.write_all(&(sequence_name.len() as u64).to_le_bytes())?;
+                writer.write_all(sequence_name.as_bytes())?;
+                writer.write_all(&(offsets.len() as u64).to_le_bytes())?;
+                for &offset in offsets {
+                    writer.write_all(&(offset as u64).to_le_bytes())?;
+                }
+                Ok(())
+            }
+            Self::Json(writer) => {
+                serde_json::to_writer(
+                    &mut *writer,
+                    &JsonEntryRef {
+                        sequence_name,
+                        offsets,
+                    },
+                )?;
+                writeln!(writer)
+            }
+            Self::Delta(writer) => {
+                write_leb128(writer, sequence_name.len() as u64)?;
+                writer.write_all(sequence_name.as_bytes())?;
+                write_leb128(writer, offsets.len() as u64)?;
+                let mut previous_offset = 0;
+                for &offset in offsets {
+                    let difference = offset.checked_sub(previous_offset).ok_or_else(|| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!("offsets of sequence {sequence_name} decrease from {previous_offset} to {offset}"),
+                        )
+                    })?;
+                    write_leb128(writer, difference as u64)?;
+                    previous_offset = offset;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Flushes all buffered entries.
+    pub fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Cbor(writer)
+            | Self::Tsv(writer)
+            | Self::Packed(writer)
+            | Self::Json(writer)
+            | Self::Delta(writer) => writer.flush(),
+        }
+    }
+}
+
+/// Writes an integer as unsigned LEB128, i.e. in groups of seven bits starting with the least significant ones,
+/// with the highest bit of each byte set if more groups follow.
+fn write_leb128(writer: &mut impl Write, mut value: u64) -> io::Result<()> {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            return writer.write_all(&[byte]);
+        }
+        writer.write_all(&[byte | 0x80])?;
+    }
+}
+
+fn cbor_to_io_error(error: ciborium::ser::Error<io::Error>) -> io::Error {
+    match error {
+        ciborium::ser::Error::Io(error) => error,
+        ciborium::ser::Error::Value(message) => io::Error::new(io::ErrorKind::InvalidData, message),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENTRIES: [(&str, &[usize]); 2] = [("q1", &[0, 1, 3, 4, 5, 8]), ("q2", &[0, 2, 3])];
+
+    fn write_map_file(format: MapFormat, entries: &[(&str, &[usize])]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut map_writer = MapWriter::new(&mut bytes, format).unwrap();
+        for (sequence_name, offsets) in entries {
+            map_writer.write_entry(sequence_name, offsets).unwrap();
+        }
+        map_writer.flush().unwrap();
+        drop(map_writer);
+        bytes
+    }
+
+    #[test]
+    fn entries_without_offsets_are_rejected() {
+        for &format in MapFormat::value_variants() {
+            let bytes = write_map_file(format, &[("q1", &[]), ENTRIES[1]]);
+            let mut entries = read_map_entries(bytes.as_slice(), format);
+            assert!(
+                matches!(entries.next(), Some(Err(MapReadError::Malformed(message))) if message.contains("q1")),
+                "{format:?} map entry without offsets is not rejected"
+            );
+        }
+    }
+
+    #[test]
+    fn older_cbor_items_are_accepted() {
+        let mut bytes = Vec::new();
+        for (sequence_name, offsets) in ENTRIES {
+            ciborium::ser::into_writer(&(sequence_name, offsets), &mut bytes).unwrap();
+        }
+        let entries: Vec<_> = read_map_entries(bytes.as_slice(), MapFormat::Cbor)
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(
+            entries,
+            ENTRIES
+                .iter()
+                .map(|(sequence_name, offsets)| (sequence_name.to_string(), offsets.to_vec()))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn cbor_items_with_wrong_compressed_length_are_rejected() {
+        let mut bytes = Vec::new();
+        let (sequence_name, offsets) = ENTRIES[0];
+        let entry = MapEntry {
+            compressed_len: offsets.len(),
+            offsets,
+        };
+        ciborium::ser::into_writer(&(sequence_name, entry), &mut bytes).unwrap();
+        let mut entries = read_map_entries(bytes.as_slice(), MapFormat::Cbor);
+        assert!(matches!(
+            entries.next(),
+            Some(Err(MapReadError::Malformed(message))) if message.contains("q1")
+        ));
+    }
+
+    #[test]
+    fn entries_with_empty_or_negative_runs_are_rejected() {
+        for (offsets, position) in [(&[0, 1, 3, 3, 4, 5][..], 2), (&[0, 5, 3, 6, 7, 8], 1)] {
+            for &format in MapFormat::value_variants() {
+                if format == MapFormat::Delta && offsets[position] > offsets[position + 1] {
+                    // Decreasing offsets cannot be stored in the delta format.
+                    let mut map_writer = MapWriter::new(Vec::new(), format).unwrap();
+                    assert!(map_writer.write_entry("q1", offsets).is_err());
+                    continue;
+                }
+                let bytes = write_map_file(format, &[("q1", offsets), ENTRIES[1]]);
+                let mut entries = read_map_entries(bytes.as_slice(), format);
+                assert!(
+                    matches!(
+                        entries.next(),
+                        Some(Err(MapReadError::Malformed(message)))
+                            if message.contains("q1") && message.contains(&format!("position {position}:"))
+                    ),
+                    "{format:?} map entry with offsets {offsets:?} is not rejected"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn delta_format_stores_short_runs_in_single_bytes() {
+        let offsets = [0, 1, 3, 4, 200, 201];
+        let bytes = write_map_file(MapFormat::Delta, &[("q1", &offsets)]);
+        // The magic bytes, the name length, the name, the offset count, and one byte per difference except 196.
+        assert_eq!(bytes.len(), 8 + 1 + 2 + 1 + 5 + 2);
+        let mut entries = read_map_entries(bytes.as_slice(), MapFormat::Delta);
+        let (sequence_name, read_offsets) = entries.next().unwrap().unwrap();
+        assert_eq!(
+            (sequence_name.as_str(), read_offsets.as_slice()),
+            ("q1", &offsets[..])
+        );
+        assert!(entries.next().is_none());
+    }
+
+    #[test]
+    fn truncated_map_files_are_detected() {
+        for &format in MapFormat::value_variants() {
+            let bytes = write_map_file(format, &ENTRIES);
+            let first_entry_end = write_map_file(format, &ENTRIES[..1]).len();
+
+            // Cut the file at every position within the last entry.
+            for cut in first_entry_end + 1..bytes.len() {
+                let mut entries = read_map_entries(&bytes[..cut], format);
+                let (sequence_name, offsets) = entries.next().unwrap().unwrap();
+                assert_eq!((sequence_name.as_str(), offsets.as_slice()), ENTRIES[0]);
+                assert!(
+                    matches!(entries.next(), Some(Err(MapReadError::Truncated(_)))),
+                    "{format:?} map file cut after {cut} of {} bytes is not detected as truncated",
+                    bytes.len()
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,307 @@
+use ciborium::value::Value;
+use minimap2_paf_io::data::PAFLine;
+use serde::ser::{Serialize, SerializeTuple, Serializer};
+use std::convert::TryFrom;
+use std::fmt::{Display, Formatter};
+use std::io::{self, BufRead, BufReader, Read};
+
+/// An alignment record of the CBOR output format.
+///
+/// In CBOR, a record is an array of thirteen items: the twelve mandatory PAF columns in their usual order,
+/// with the sequence names and the strand as text and all other columns as unsigned integers,
+/// followed by an array of the optional fields as text in the form `tag:type:value`.
+/// The strand is stored as it is formatted in the text output, so it depends on `--strand-format`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PafRecord {
+    /// The name of the query sequence.
+    pub query_sequence_name: String,
+    /// The length of the query sequence.
+    pub query_sequence_length: usize,
+    /// The start coordinate of the alignment on the query sequence.
+    pub query_start_coordinate: usize,
+    /// The end coordinate of the alignment on the query sequence.
+    pub query_end_coordinate: usize,
+    /// The strand of the alignment.
+    pub strand: String,
+    /// The name of the target sequence.
+    pub target_sequence_name: String,
+    /// The length of the target sequence.
+    pub target_sequence_length: usize,
+    /// The start coordinate of the alignment on the original strand of the target sequence.
+    pub target_start_coordinate: usize,
+    /// The end coordinate of the alignment on the original strand of the target sequence.
+    pub target_end_coordinate: usize,
+    /// The number of matching bases.
+    pub number_of_matching_bases: usize,
+    /// The number of bases and gaps in the alignment.
+    pub number_of_bases_and_gaps: usize,
+    /// The mapping quality.
+    pub mapping_quality: usize,
+    /// The optional fields, e.g. `cs:Z::4`.
+    pub optional_fields: Vec<String>,
+}
+
+/// An error that occurred while reading a file of CBOR encoded [PafRecord]s.
+#[derive(Debug)]
+pub enum PafRecordReadError {
+    /// The file could not be read.
+    Io(io::Error),
+    /// A record is malformed or incomplete.
+    Malformed(String),
+}
+
+impl PafRecord {
+    /// Creates a record from a PAF line, with the strand formatted as given.
+    /// The optional fields are in the same order as in the text serialisation of the PAF line.
+    pub fn from_paf_line(paf_line: &PAFLine, strand: &str) -> Self {
+        Self {
+            query_sequence_name: paf_line.query_sequence_name.clone(),
+            query_sequence_length: paf_line.query_sequence_length,
+            query_start_coordinate: paf_line.query_start_coordinate,
+            query_end_coordinate: paf_line.query_end_coordinate,
+            strand: strand.to_string(),
+            target_sequence_name: paf_line.target_sequence_name.clone(),
+            target_sequence_length: paf_line.target_sequence_length,
+            target_start_coordinate: paf_line.target_start_coordinate_on_original_strand,
+            target_end_coordinate: paf_line.target_end_coordinate_on_original_strand,
+            number_of_matching_bases: paf_line.number_of_matching_bases,
+            number_of_bases_and_gaps: paf_line.number_of_bases_and_gaps,
+            mapping_quality: paf_line.mapping_quality.into(),
+            optional_fields: optional_fields(paf_line),
+        }
+    }
+
+    /// Parses a record from a line of the text output.
+    /// Returns `None` if the line does not have the twelve mandatory columns
+    /// or if a numeric column is not an unsigned integer.
+    pub fn from_line(line: &str) -> Option<Self> {
+        let mut columns = line.split('\t');
+        let mut text = || columns.next().map(str::to_string);
+        let query_sequence_name = text()?;
+        let query_sequence_length = text()?.parse().ok()?;
+        let query_start_coordinate = text()?.parse().ok()?;
+        let query_end_coordinate = text()?.parse().ok()?;
+        let strand = text()?;
+        let target_sequence_name = text()?;
+        let target_sequence_length = text()?.parse().ok()?;
+        let target_start_coordinate = text()?.parse().ok()?;
+        let target_end_coordinate = text()?.parse().ok()?;
+        let number_of_matching_bases = text()?.parse().ok()?;
+        let number_of_bases_and_gaps = text()?.parse().ok()?;
+        let mapping_quality = text()?.parse().ok()?;
+        Some(Self {
+            query_sequence_name,
+            query_sequence_length,
+            query_start_coordinate,
+            query_end_coordinate,
+            strand,
+            target_sequence_name,
+            target_sequence_length,
+            target_start_coordinate,
+            target_end_coordinate,
+            number_of_matching_bases,
+            number_of_bases_and_gaps,
+            mapping_quality,
+            optional_fields: columns.map(str::to_string).collect(),
+        })
+    }
+
+    /// Encodes the record as CBOR.
+    pub fn to_cbor(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        // Encoding into memory only fails for unsupported types, which are not used here.
+        ciborium::ser::into_writer(self, &mut bytes).unwrap();
+        bytes
+    }
+
+    /// Decodes a record from a CBOR item, or returns `None` if the item does not follow the schema.
+    fn from_cbor(item: Value) -> Option<Self> {
+        let mut items = if let Value::Array(items) = item {
+            items.into_iter()
+        } else {
+            return None;
+        };
+        if items.len() != 13 {
+            return None;
+        }
+        Some(Self {
+            query_sequence_name: cbor_text(items.next()?)?,
+            query_sequence_length: cbor_usize(items.next()?)?,
+            query_start_coordinate: cbor_usize(items.next()?)?,
+            query_end_coordinate: cbor_usize(items.next()?)?,
+            strand: cbor_text(items.next()?)?,
+            target_sequence_name: cbor_text(items.next()?)?,
+            target_sequence_length: cbor_usize(items.next()?)?,
+            target_start_coordinate: cbor_usize(items.next()?)?,
+            target_end_coordinate: cbor_usize(items.next()?)?,
+            number_of_matching_bases: cbor_usize(items.next()?)?,
+            number_of_bases_and_gaps: cbor_usize(items.next()?)?,
+            mapping_quality: cbor_usize(items.next()?)?,
+            optional_fields: if let Value::Array(optional_fields) = items.next()? {
+                optional_fields
+                    .into_iter()
+                    .map(cbor_text)
+                    .collect::<Option<_>>()?
+            } else {
+                return None;
+            },
+        })
+    }
+}
+
+/// Serialises the optional fields of a PAF line, in the order used by minimap2.
+fn optional_fields(paf_line: &PAFLine) -> Vec<String> {
+    fn push<T: Display>(optional_fields: &mut Vec<String>, tag: &str, value: &Option<T>) {
+        if let Some(value) = value {
+            optional_fields.push(format!("{tag}:{value}"));
+        }
+    }
+
+    let mut optional_fields = Vec::new();
+    let fields = &mut optional_fields;
+    push(
+        fields,
+        "NM:i",
+        &paf_line.total_number_of_mismatches_and_gaps,
+    );
+    push(fields, "ms:i", &paf_line.best_segment_dp_score);
+    push(fields, "AS:i", &paf_line.dp_alignment_score);
+    push(fields, "nn:i", &paf_line.number_of_ambiguous_bases);
+    push(fields, "tp:A", &paf_line.alignment_type);
+    push(fields, "cm:i", &paf_line.number_of_minimisers);
+    push(fields, "s1:i", &paf_line.chaining_score);
+    push(fields, "s2:i", &paf_line.best_secondary_chaining_score);
+    push(
+        fields,
+        "de:f",
+        &paf_line.gap_compressed_per_base_sequence_divergence,
+    );
+    push(
+        fields,
+        "rl:i",
+        &paf_line.length_of_query_regions_with_repetitive_seeds,
+    );
+    push(fields, "MD:Z", &paf_line.unknown_md);
+    push(fields, "SA:Z", &paf_line.supplementary_alignments);
+    push(fields, "ts:A", &paf_line.transcript_strand);
+    push(fields, "cg:Z", &paf_line.cigar_string);
+    push(fields, "cs:Z", &paf_line.difference_string);
+    push(
+        fields,
+        "dv:f",
+        &paf_line.approximate_per_base_sequence_divergence,
+    );
+    optional_fields.extend(paf_line.unknown_fields.iter().cloned());
+    optional_fields
+}
+
+fn cbor_text(item: Value) -> Option<String> {
+    if let Value::Text(text) = item {
+        Some(text)
+    } else {
+        None
+    }
+}
+
+fn cbor_usize(item: Value) -> Option<usize> {
+    if let Value::Integer(number) = item {
+        usize::try_from(number).ok()
+    } else {
+        None
+    }
+}
+
+impl Serialize for PafRecord {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut tuple = serializer.serialize_tuple(13)?;
+        tuple.serialize_element(&self.query_sequence_name)?;
+        tuple.serialize_element(&self.query_sequence_length)?;
+        tuple.serialize_element(&self.query_start_coordinate)?;
+        tuple.serialize_element(&self.query_end_coordinate)?;
+        tuple.serialize_element(&self.strand)?;
+        tuple.serialize_element(&self.target_sequence_name)?;
+        tuple.serialize_element(&self.target_sequence_length)?;
+        tuple.serialize_element(&self.target_start_coordinate)?;
+        tuple.serialize_element(&self.target_end_coordinate)?;
+        tuple.serialize_element(&self.number_of_matching_bases)?;
+        tuple.serialize_element(&self.number_of_bases_and_gaps)?;
+        tuple.serialize_element(&self.mapping_quality)?;
+        tuple.serialize_element(&self.optional_fields)?;
+        tuple.end()
+    }
+}
+
+/// Formats the record as a line of the text output, without line feed.
+impl Display for PafRecord {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            self.query_sequence_name,
+            self.query_sequence_length,
+            self.query_start_coordinate,
+            self.query_end_coordinate,
+            self.strand,
+            self.target_sequence_name,
+            self.target_sequence_length,
+            self.target_start_coordinate,
+            self.target_end_coordinate,
+            self.number_of_matching_bases,
+            self.number_of_bases_and_gaps,
+            self.mapping_quality,
+        )?;
+        for optional_field in &self.optional_fields {
+            write!(f, "\t{optional_field}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for PafRecordReadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PafRecordReadError::Io(error) => write!(f, "{error}"),
+            PafRecordReadError::Malformed(message) => write!(f, "malformed record: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for PafRecordReadError {}
+
+/// An iterator over the [PafRecord]s of a file in the CBOR output format.
+pub struct PafRecordReader<R> {
+    reader: BufReader<R>,
+}
+
+impl<R: Read> PafRecordReader<R> {
+    /// Creates an iterator over the records read from the given reader.
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader: BufReader::new(reader),
+        }
+    }
+}
+
+impl<R: Read> Iterator for PafRecordReader<R> {
+    type Item = Result<PafRecord, PafRecordReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // The file may only end between records.
+        match self.reader.fill_buf() {
+            Ok([]) => return None,
+            Ok(_) => {}
+            Err(error) => return Some(Err(PafRecordReadError::Io(error))),
+        }
+
+        Some(match ciborium::de::from_reader(&mut self.reader) {
+            Ok(item) => PafRecord::from_cbor(item).ok_or_else(|| {
+                PafRecordReadError::Malformed(
+                    "not an array of twelve PAF columns and an array of optional fields"
+                        .to_string(),
+                )
+            }),
+            Err(ciborium::de::Error::Io(error)) => Err(PafRecordReadError::Io(error)),
+            Err(error) => Err(PafRecordReadError::Malformed(format!("{error:?}"))),
+        })
+    }
+}
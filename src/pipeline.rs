@@ -0,0 +1,54 @@
+use crate::{hodeco_paf_line, CoordinateMap, HodecoError, HodecoMap};
+use minimap2_paf_io::data::PAFLine;
+use std::collections::HashMap;
+
+/// An iterator that homopolymer decompresses PAF lines using hodeco maps held in memory.
+///
+/// The iterator borrows the query and target hodeco maps for the lifetime `'maps`,
+/// so the maps must outlive it, but are never copied.
+/// Embedders that already hold their maps as offset vectors can move them into [HodecoMap]s
+/// without copying via [HodecoMap::from].
+/// Maps of other compression schemes can be used by implementing [CoordinateMap].
+///
+/// Each item is the decompressed line, or the error that occurred while decompressing it.
+/// Errors do not end the iteration.
+pub struct DecompressIter<'maps, Lines, Map = HodecoMap> {
+    hoco_paf_lines: Lines,
+    query_hodeco_maps: &'maps HashMap<String, Map>,
+    target_hodeco_maps: &'maps HashMap<String, Map>,
+}
+
+impl<'maps, Lines: Iterator<Item = PAFLine>, Map: CoordinateMap> DecompressIter<'maps, Lines, Map> {
+    /// Creates an iterator that decompresses the given PAF lines.
+    pub fn new(
+        hoco_paf_lines: impl IntoIterator<IntoIter = Lines>,
+        query_hodeco_maps: &'maps HashMap<String, Map>,
+        target_hodeco_maps: &'maps HashMap<String, Map>,
+    ) -> Self {
+        Self {
+            hoco_paf_lines: hoco_paf_lines.into_iter(),
+            query_hodeco_maps,
+            target_hodeco_maps,
+        }
+    }
+}
+
+impl<'maps, Lines: Iterator<Item = PAFLine>, Map: CoordinateMap> Iterator
+    for DecompressIter<'maps, Lines, Map>
+{
+    type Item = Result<PAFLine, HodecoError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.hoco_paf_lines.next().map(|hoco_paf_line| {
+            hodeco_paf_line(
+                hoco_paf_line,
+                self.query_hodeco_maps,
+                self.target_hodeco_maps,
+            )
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.hoco_paf_lines.size_hint()
+    }
+}
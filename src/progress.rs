@@ -0,0 +1,183 @@
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use log::{info, Log, Metadata, Record};
+use std::io::{self, Read};
+use std::time::{Duration, Instant};
+
+/// Returns the container for progress bars if stderr is a terminal, and `None` otherwise.
+pub fn terminal_progress_bars() -> Option<MultiProgress> {
+    let draw_target = ProgressDrawTarget::stderr();
+    if draw_target.is_hidden() {
+        None
+    } else {
+        Some(MultiProgress::with_draw_target(draw_target))
+    }
+}
+
+/// A logger that hides the progress bars while it writes a message.
+/// Both write to stderr, so log messages would otherwise be drawn into the bars.
+pub struct ProgressBarLogger<L> {
+    logger: L,
+    progress_bars: MultiProgress,
+}
+
+impl<L> ProgressBarLogger<L> {
+    /// Wraps the given logger.
+    pub fn new(logger: L, progress_bars: MultiProgress) -> Self {
+        Self {
+            logger,
+            progress_bars,
+        }
+    }
+}
+
+impl<L: Log> Log for ProgressBarLogger<L> {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.logger.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if self.logger.enabled(record.metadata()) {
+            self.progress_bars.suspend(|| self.logger.log(record));
+        }
+    }
+
+    fn flush(&self) {
+        self.logger.flush();
+    }
+}
+
+/// Periodically logs the progress of a phase of the computation,
+/// or displays it as a progress bar.
+pub struct Progress {
+    phase: String,
+    unit: &'static str,
+    total: Option<u64>,
+    done: u64,
+    interval: Option<Duration>,
+    start: Instant,
+    last_report: Instant,
+    bar: Option<ProgressBar>,
+}
+
+impl Progress {
+    /// Creates a progress reporter for the given phase.
+    /// If `interval` is `None`, then nothing is reported.
+    /// Otherwise, if `progress_bars` is given, the progress is displayed as a bar in it instead of being logged.
+    pub fn new(
+        phase: String,
+        unit: &'static str,
+        total: Option<u64>,
+        interval: Option<Duration>,
+        progress_bars: Option<&MultiProgress>,
+    ) -> Self {
+        let bar = progress_bars
+            .filter(|_| interval.is_some())
+            .map(|progress_bars| {
+                let (bar, template) = if let Some(total) = total {
+                    (
+                        ProgressBar::new(total),
+                        "{prefix} [{elapsed_precise}] [{bar:40}] {human_pos}/{human_len} {msg}",
+                    )
+                } else {
+                    (
+                        ProgressBar::new_spinner(),
+                        "{prefix} [{elapsed_precise}] {spinner} {human_pos} {msg}",
+                    )
+                };
+                // The templates are constant and valid.
+                bar.set_style(ProgressStyle::with_template(template).unwrap());
+                bar.set_prefix(phase.clone());
+                bar.set_message(unit);
+                progress_bars.add(bar)
+            });
+        let now = Instant::now();
+        Self {
+            phase,
+            unit,
+            total,
+            done: 0,
+            interval,
+            start: now,
+            last_report: now,
+            bar,
+        }
+    }
+
+    /// Records that `amount` more units were processed, and reports if the interval has elapsed.
+    /// A progress bar is advanced immediately, but its rate is only updated when the interval has elapsed.
+    pub fn advance(&mut self, amount: u64) {
+        self.done += amount;
+        if let Some(bar) = &self.bar {
+            bar.inc(amount);
+        }
+        if let Some(interval) = self.interval {
+            let now = Instant::now();
+            if now.duration_since(self.last_report) >= interval {
+                self.last_report = now;
+                self.report(now);
+            }
+        }
+    }
+
+    /// Reports the final progress of the phase.
+    pub fn finish(&self) {
+        if self.interval.is_some() {
+            self.report(Instant::now());
+        }
+        if let Some(bar) = &self.bar {
+            bar.finish();
+        }
+    }
+
+    fn report(&self, now: Instant) {
+        let seconds = now.duration_since(self.start).as_secs_f64();
+        let rate = if seconds > 0.0 {
+            self.done as f64 / seconds
+        } else {
+            0.0
+        };
+        if let Some(bar) = &self.bar {
+            bar.set_message(format!("{} ({rate:.0} {}/s)", self.unit, self.unit));
+            return;
+        }
+        let percentage = match self.total {
+            Some(total) if total > 0 => {
+                format!(
+                    " of {total} ({:.1}%)",
+                    self.done as f64 / total as f64 * 100.0
+                )
+            }
+            _ => String::new(),
+        };
+        info!(
+            "[{}] {} {}{percentage} after {seconds:.0}s ({rate:.0} {}/s)",
+            self.phase, self.done, self.unit, self.unit
+        );
+    }
+}
+
+/// A reader that reports the number of bytes read through it.
+pub struct ProgressReader<R> {
+    inner: R,
+    progress: Progress,
+}
+
+impl<R> ProgressReader<R> {
+    /// Wraps the given reader.
+    pub fn new(inner: R, progress: Progress) -> Self {
+        Self { inner, progress }
+    }
+
+    /// Reports the final progress.
+    pub fn finish(&self) {
+        self.progress.finish();
+    }
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let amount = self.inner.read(buffer)?;
+        self.progress.advance(amount as u64);
+        Ok(amount)
+    }
+}
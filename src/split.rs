@@ -0,0 +1,230 @@
+use crate::count_difference_column;
+use log::warn;
+use minimap2_paf_io::data::{AlignmentDifference, Cigar, CigarColumn, DifferenceColumn, PAFLine};
+
+/// Splits a homopolymer decompressed PAF line at each insertion or deletion that is longer than `max_gap_length`.
+///
+/// Such long gaps are likely artifacts of a single compressed character that maps to a huge decompressed run,
+/// e.g. an assembly gap encoded as a run of `N`s.
+/// The gap itself is removed, and the parts of the alignment before and after it become separate records
+/// with their own coordinates, CIGAR string, difference string and statistics.
+/// Without CIGAR string, the numbers of matching bases and of bases and gaps are recomputed from the difference string.
+/// The mismatches and gaps are counted like [crate::hodeco_paf_line] counts them.
+/// All other fields, including the divergence values, are copied from the original line.
+///
+/// The gaps are located in the CIGAR string if present, and otherwise in the difference string.
+/// If both are present, they must contain the same long gaps, otherwise the line is not split.
+/// Lines without CIGAR and difference string are never split.
+pub fn split_on_gaps(hodeco_paf: PAFLine, max_gap_length: usize) -> Vec<PAFLine> {
+    let cigar_segments = hodeco_paf
+        .cigar_string
+        .as_ref()
+        .map(|cigar_string| split_cigar(cigar_string, max_gap_length));
+    let difference_segments = hodeco_paf
+        .difference_string
+        .as_ref()
+        .map(|difference_string| split_difference_string(difference_string, max_gap_length));
+
+    let segment_count = match (&cigar_segments, &difference_segments) {
+        (Some(cigar_segments), Some(difference_segments)) => {
+            if cigar_segments.len() != difference_segments.len()
+                || cigar_segments.iter().zip(difference_segments).any(
+                    |(cigar_segment, difference_segment)| {
+                        cigar_segment.range != difference_segment.range
+                    },
+                )
+            {
+                warn!(
+                    "CIGAR string and difference string of alignment between query {} and target {} have different long gaps, not splitting",
+                    hodeco_paf.query_sequence_name, hodeco_paf.target_sequence_name
+                );
+                return vec![hodeco_paf];
+            }
+            cigar_segments.len()
+        }
+        (Some(segments), None) => segments.len(),
+        (None, Some(segments)) => segments.len(),
+        (None, None) => 1,
+    };
+    if segment_count == 1 {
+        return vec![hodeco_paf];
+    }
+
+    (0..segment_count)
+        .map(|index| {
+            let mut segment_paf = hodeco_paf.clone();
+            let range = if let Some(cigar_segments) = &cigar_segments {
+                cigar_segments[index].range
+            } else {
+                difference_segments.as_ref().unwrap()[index].range
+            };
+
+            segment_paf.target_start_coordinate_on_original_strand =
+                hodeco_paf.target_start_coordinate_on_original_strand + range.target_start;
+            segment_paf.target_end_coordinate_on_original_strand =
+                hodeco_paf.target_start_coordinate_on_original_strand + range.target_end;
+            if hodeco_paf.strand {
+                segment_paf.query_start_coordinate =
+                    hodeco_paf.query_start_coordinate + range.query_start;
+                segment_paf.query_end_coordinate =
+                    hodeco_paf.query_start_coordinate + range.query_end;
+            } else {
+                // On the reverse strand, the alignment starts at the end of the query.
+                segment_paf.query_start_coordinate =
+                    hodeco_paf.query_end_coordinate - range.query_end;
+                segment_paf.query_end_coordinate =
+                    hodeco_paf.query_end_coordinate - range.query_start;
+            }
+
+            if let Some(cigar_segments) = &cigar_segments {
+                let cigar_string = Cigar(cigar_segments[index].columns.to_vec());
+                segment_paf.number_of_matching_bases = 0;
+                segment_paf.number_of_bases_and_gaps = 0;
+                for cigar_column in &cigar_string.0 {
+                    match cigar_column {
+                        CigarColumn::Match(count) => {
+                            segment_paf.number_of_matching_bases += count;
+                            segment_paf.number_of_bases_and_gaps += count;
+                        }
+                        CigarColumn::Insertion(count)
+                        | CigarColumn::Deletion(count)
+                        | CigarColumn::Mismatch(count) => {
+                            segment_paf.number_of_bases_and_gaps += count
+                        }
+                    }
+                }
+                segment_paf.cigar_string = Some(cigar_string);
+            }
+
+            if let Some(difference_segments) = &difference_segments {
+                let difference_string =
+                    AlignmentDifference(difference_segments[index].columns.to_vec());
+                segment_paf.total_number_of_mismatches_and_gaps =
+                    Some(count_mismatches_and_gaps(&difference_string.0));
+                if cigar_segments.is_none() {
+                    segment_paf.number_of_matching_bases = 0;
+                    segment_paf.number_of_bases_and_gaps = 0;
+                    for difference_column in &difference_string.0 {
+                        let (query_length, target_length, _) =
+                            measure_difference_column(difference_column);
+                        if let DifferenceColumn::Match { length } = difference_column {
+                            segment_paf.number_of_matching_bases += length;
+                        }
+                        segment_paf.number_of_bases_and_gaps += query_length.max(target_length);
+                    }
+                }
+                segment_paf.difference_string = Some(difference_string);
+            }
+
+            segment_paf
+        })
+        .collect()
+}
+
+/// The part of an alignment covered by a segment, relative to the start of the alignment.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+struct SegmentRange {
+    query_start: usize,
+    query_end: usize,
+    target_start: usize,
+    target_end: usize,
+}
+
+struct Segment<'columns, Column> {
+    columns: &'columns [Column],
+    range: SegmentRange,
+}
+
+/// Splits a sequence of alignment columns at each column that is a gap longer than `max_gap_length`.
+/// The `measure` function returns the query length, the target length and whether the column is a gap.
+fn split_columns<Column>(
+    columns: &[Column],
+    max_gap_length: usize,
+    measure: impl Fn(&Column) -> (usize, usize, bool),
+) -> Vec<Segment<'_, Column>> {
+    let mut segments = Vec::new();
+    let mut segment_start_index = 0;
+    let mut range = SegmentRange::default();
+
+    for (index, column) in columns.iter().enumerate() {
+        let (query_length, target_length, is_gap) = measure(column);
+
+        if is_gap && query_length.max(target_length) > max_gap_length {
+            segments.push(Segment {
+                columns: &columns[segment_start_index..index],
+                range,
+            });
+            segment_start_index = index + 1;
+            range.query_start = range.query_end + query_length;
+            range.target_start = range.target_end + target_length;
+        }
+
+        range.query_end += query_length;
+        range.target_end += target_length;
+    }
+
+    segments.push(Segment {
+        columns: &columns[segment_start_index..],
+        range,
+    });
+    // Gaps at the very start or end of an alignment do not separate two alignments.
+    segments.retain(|segment| !segment.columns.is_empty());
+    segments
+}
+
+fn split_cigar(cigar_string: &Cigar, max_gap_length: usize) -> Vec<Segment<'_, CigarColumn>> {
+    split_columns(
+        &cigar_string.0,
+        max_gap_length,
+        |cigar_column| match cigar_column {
+            CigarColumn::Match(count) | CigarColumn::Mismatch(count) => (*count, *count, false),
+            CigarColumn::Insertion(count) => (*count, 0, true),
+            CigarColumn::Deletion(count) => (0, *count, true),
+        },
+    )
+}
+
+fn split_difference_string(
+    difference_string: &AlignmentDifference,
+    max_gap_length: usize,
+) -> Vec<Segment<'_, DifferenceColumn>> {
+    split_columns(
+        &difference_string.0,
+        max_gap_length,
+        measure_difference_column,
+    )
+}
+
+/// Returns the query length, the target length and whether the difference column is a gap.
+fn measure_difference_column(difference_column: &DifferenceColumn) -> (usize, usize, bool) {
+    match difference_column {
+        DifferenceColumn::Match { length } => (*length, *length, false),
+        DifferenceColumn::Mismatch { .. } => (1, 1, false),
+        DifferenceColumn::Insertion {
+            superfluous_query_characters,
+        } => (superfluous_query_characters.len(), 0, true),
+        DifferenceColumn::Deletion {
+            missing_query_characters,
+        } => (0, missing_query_characters.len(), true),
+    }
+}
+
+/// Counts the mismatches and gaps of decompressed difference columns.
+///
+/// Decompression expands each mismatch into adjacent copies, and only the additional copies are counted.
+/// Adjacent characters of a homopolymer compressed sequence differ,
+/// so each run of equal adjacent mismatches stems from a single compressed mismatch.
+fn count_mismatches_and_gaps(difference_columns: &[DifferenceColumn]) -> usize {
+    let mut total_number_of_mismatches_and_gaps = 0;
+    for (index, difference_column) in difference_columns.iter().enumerate() {
+        let is_additional_mismatch = matches!(difference_column, DifferenceColumn::Mismatch { .. })
+            && index > 0
+            && difference_columns[index - 1] == *difference_column;
+        count_difference_column(
+            difference_column,
+            usize::from(is_additional_mismatch),
+            &mut total_number_of_mismatches_and_gaps,
+        );
+    }
+    total_number_of_mismatches_and_gaps
+}
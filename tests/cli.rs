@@ -0,0 +1,650 @@
+mod common;
+
+use common::{
+    assert_exit_code, parse_tsv_maps, TestDirectory, INPUT_PAF, OUTPUT_PAF, QUERY_MAP_TSV,
+    TARGET_MAP_TSV,
+};
+use minimap2_homopolymer_decompression::PafRecordReader;
+use noodles_bgzf as bgzf;
+use std::io::Read;
+
+#[test]
+fn decompresses_example() {
+    let directory = TestDirectory::with_example_maps("decompresses_example");
+    directory.write("input.paf", INPUT_PAF);
+    assert_exit_code(&directory.decompress(&[]), 0);
+    assert_eq!(directory.read_to_string("output.paf"), OUTPUT_PAF);
+}
+
+#[test]
+fn single_threaded_and_multi_threaded_output_is_identical() {
+    let directory = TestDirectory::with_example_maps("single_and_multi_threaded");
+    directory.write("input.paf", INPUT_PAF.repeat(200));
+
+    let mut outputs = Vec::new();
+    for compute_threads in ["0", "1", "4"] {
+        assert_exit_code(
+            &directory.decompress(&["--compute-threads", compute_threads, "--queue-size", "2"]),
+            0,
+        );
+        outputs.push(directory.read_to_string("output.paf"));
+    }
+    assert_eq!(outputs[0], OUTPUT_PAF.repeat(200));
+    // With a single compute thread, the order of the alignments is preserved.
+    assert_eq!(outputs[0], outputs[1]);
+    // With multiple compute threads, the alignments may be reordered.
+    let sorted_lines = |output: &str| {
+        let mut lines: Vec<_> = output.lines().map(str::to_string).collect();
+        lines.sort();
+        lines
+    };
+    assert_eq!(sorted_lines(&outputs[0]), sorted_lines(&outputs[2]));
+}
+
+#[test]
+fn strand_formats() {
+    let directory = TestDirectory::with_example_maps("strand_formats");
+    directory.write("input.paf", INPUT_PAF);
+    for (strand_format, forward, reverse) in [
+        ("plus-minus", "+", "-"),
+        ("zero-one", "0", "1"),
+        ("fwd-rev", "fwd", "rev"),
+    ] {
+        assert_exit_code(
+            &directory.decompress(&["--strand-format", strand_format]),
+            0,
+        );
+        let output = directory.read_to_string("output.paf");
+        assert_eq!(output.lines().count(), 2);
+        for ((line, expected_line), strand) in output
+            .lines()
+            .zip(OUTPUT_PAF.lines())
+            .zip([forward, reverse])
+        {
+            let mut columns: Vec<_> = line.split('\t').collect();
+            assert_eq!(columns[4], strand, "--strand-format {strand_format}");
+            // The other columns are not affected.
+            columns[4] = expected_line.split('\t').nth(4).unwrap();
+            assert_eq!(columns.join("\t"), expected_line);
+        }
+    }
+}
+
+#[test]
+fn one_based_output_coordinates_round_trip() {
+    let directory = TestDirectory::with_example_maps("one_based_output_coordinates");
+    directory.write("input.paf", INPUT_PAF);
+    for (interval_convention, closed_offset, first_line_coordinates) in [
+        ("half-open", 0, ["1", "15", "2", "17"]),
+        ("closed", 1, ["1", "14", "2", "16"]),
+    ] {
+        assert_exit_code(
+            &directory.decompress(&[
+                "--coordinate-output-base",
+                "1",
+                "--coordinate-output-interval",
+                interval_convention,
+            ]),
+            0,
+        );
+        let output = directory.read_to_string("output.paf");
+        let first_line: Vec<_> = output.lines().next().unwrap().split('\t').collect();
+        assert_eq!(
+            [first_line[2], first_line[3], first_line[7], first_line[8]],
+            first_line_coordinates
+        );
+
+        // Converting the coordinates back to zero-based half-open intervals yields the default output.
+        let zero_based: String = output
+            .lines()
+            .map(|line| {
+                let mut columns: Vec<_> = line.split('\t').map(str::to_string).collect();
+                for (start_column, end_column) in [(2, 3), (7, 8)] {
+                    let start: usize = columns[start_column].parse().unwrap();
+                    let end: usize = columns[end_column].parse().unwrap();
+                    columns[start_column] = (start - 1).to_string();
+                    columns[end_column] = (end - 1 + closed_offset).to_string();
+                }
+                columns.join("\t") + "\n"
+            })
+            .collect();
+        assert_eq!(
+            zero_based, OUTPUT_PAF,
+            "--coordinate-output-interval {interval_convention}"
+        );
+    }
+}
+
+#[test]
+fn divergence_out_of_range_is_reported_and_clamped() {
+    let directory = TestDirectory::with_example_maps("divergence_out_of_range");
+    // Without difference string, the divergence is rescaled by the query expansion of 14 / 10.
+    directory.write(
+        "input.paf",
+        "q1\t10\t0\t10\t+\tt1\t12\t1\t12\t8\t11\t60\tcg:Z:4M1I3M2D2M\tdv:f:0.9\n",
+    );
+    let divergence = |output: &str| -> f64 {
+        output
+            .trim_end()
+            .rsplit('\t')
+            .next()
+            .unwrap()
+            .strip_prefix("dv:f:")
+            .unwrap()
+            .parse()
+            .unwrap()
+    };
+
+    let output = directory.decompress(&[]);
+    assert_exit_code(&output, 0);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("of alignment between query q1 and target t1 is outside of [0, 1]"));
+    assert!(!stderr.contains("clamping"));
+    assert!((divergence(&directory.read_to_string("output.paf")) - 1.26).abs() < 1e-9);
+
+    let output = directory.decompress(&["--clamp-divergence"]);
+    assert_exit_code(&output, 0);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("is outside of [0, 1], clamping"));
+    assert_eq!(divergence(&directory.read_to_string("output.paf")), 1.0);
+}
+
+#[test]
+fn keep_tags_and_drop_tags() {
+    let directory = TestDirectory::with_example_maps("keep_tags_and_drop_tags");
+    directory.write("input.paf", INPUT_PAF);
+    let tags = |output: &str| -> Vec<Vec<String>> {
+        output
+            .lines()
+            .map(|line| {
+                line.split('\t')
+                    .skip(12)
+                    .map(|field| field.split(':').next().unwrap().to_string())
+                    .collect()
+            })
+            .collect()
+    };
+
+    assert_exit_code(&directory.decompress(&["--keep-tags", "NM,cg,zz"]), 0);
+    let output = directory.read_to_string("output.paf");
+    assert_eq!(tags(&output), [vec!["NM", "cg", "zz"], vec!["NM", "cg"]]);
+    assert!(output.contains("\tcg:Z:5M3I4M3D2M\t"));
+
+    assert_exit_code(&directory.decompress(&["--drop-tags", "cs,de,zz"]), 0);
+    let output = directory.read_to_string("output.paf");
+    assert_eq!(tags(&output), [vec!["NM", "cg", "dv"], vec!["NM", "cg"]]);
+    // The mandatory columns are not affected.
+    for (line, expected_line) in output.lines().zip(OUTPUT_PAF.lines()) {
+        assert!(
+            expected_line.starts_with(&line.split('\t').take(12).collect::<Vec<_>>().join("\t"))
+        );
+    }
+}
+
+#[test]
+fn exit_codes() {
+    let directory = TestDirectory::with_example_maps("exit_codes");
+    // The query length of the second line does not match its hodeco map.
+    let invalid_input = INPUT_PAF.replace("q2\t6\t", "q2\t7\t");
+    directory.write("input.paf", &invalid_input);
+
+    // Invalid command line arguments.
+    assert_exit_code(&directory.decompress(&["--no-such-option"]), 2);
+    // Skipped lines.
+    assert_exit_code(&directory.decompress(&["--on-error", "skip"]), 3);
+    assert_eq!(
+        directory.read_to_string("output.paf"),
+        OUTPUT_PAF.lines().next().unwrap().to_string() + "\n"
+    );
+    // Failed validation.
+    assert_exit_code(&directory.decompress(&[]), 4);
+    // Missing input file.
+    assert_exit_code(
+        &directory.run(&[
+            "--input",
+            "missing.paf",
+            "--output",
+            "output.paf",
+            "--query-hodeco-map",
+            "query.tsv",
+            "--target-hodeco-map",
+            "target.tsv",
+            "--hodeco-map-format",
+            "tsv",
+        ]),
+        5,
+    );
+    // Success.
+    directory.write("input.paf", INPUT_PAF);
+    assert_exit_code(&directory.decompress(&[]), 0);
+}
+
+#[test]
+fn interleaved_comment_lines() {
+    let directory = TestDirectory::with_example_maps("interleaved_comment_lines");
+    let mut input_lines = INPUT_PAF.lines();
+    let (first_line, second_line) = (input_lines.next().unwrap(), input_lines.next().unwrap());
+    directory.write(
+        "input.paf",
+        format!("#header\n{first_line}\n# between\tlines\n{second_line}\n#footer\n"),
+    );
+
+    assert_exit_code(&directory.decompress(&[]), 0);
+    assert_eq!(directory.read_to_string("output.paf"), OUTPUT_PAF);
+
+    assert_exit_code(
+        &directory.decompress(&["--passthrough-comments", "--compute-threads", "0"]),
+        0,
+    );
+    let mut output_lines = OUTPUT_PAF.lines();
+    let (first_line, second_line) = (output_lines.next().unwrap(), output_lines.next().unwrap());
+    assert_eq!(
+        directory.read_to_string("output.paf"),
+        format!("#header\n{first_line}\n# between\tlines\n{second_line}\n#footer\n")
+    );
+}
+
+/// The empty block that marks the end of a BGZF file.
+const BGZF_EOF_BLOCK: &[u8] = &[
+    0x1f, 0x8b, 0x08, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x06, 0x00, 0x42, 0x43, 0x02, 0x00,
+    0x1b, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+#[test]
+fn bgzf_output_decompresses_to_text_output() {
+    let directory = TestDirectory::with_example_maps("bgzf_output");
+    directory.write("input.paf", INPUT_PAF.repeat(1000));
+    assert_exit_code(&directory.decompress(&[]), 0);
+    let text_output = directory.read("output.paf");
+    assert_exit_code(&directory.decompress(&["--bgzf"]), 0);
+    let bgzf_output = directory.read("output.paf");
+    assert!(bgzf_output.len() < text_output.len() / 10);
+    assert!(bgzf_output.ends_with(BGZF_EOF_BLOCK));
+
+    let mut decompressed = Vec::new();
+    bgzf::Reader::new(bgzf_output.as_slice())
+        .read_to_end(&mut decompressed)
+        .unwrap();
+    assert_eq!(decompressed, text_output);
+}
+
+#[test]
+fn convert_map_round_trip_through_all_formats() {
+    let directory = TestDirectory::with_example_maps("convert_map_round_trip");
+    let convert = |from: &str, to: &str, input: &str, output: &str| {
+        assert_exit_code(
+            &directory.run(&["convert-map", "--from", from, "--to", to, input, output]),
+            0,
+        );
+    };
+    convert("tsv", "cbor", "query.tsv", "query.cbor");
+    convert("cbor", "packed", "query.cbor", "query.packed");
+    convert("packed", "json", "query.packed", "query.json");
+    convert("json", "delta", "query.json", "query.delta");
+    convert("delta", "cbor", "query.delta", "round_trip.cbor");
+
+    // Decode both CBOR files through the TSV format.
+    convert("cbor", "tsv", "query.cbor", "query_from_cbor.tsv");
+    convert("cbor", "tsv", "round_trip.cbor", "round_trip.tsv");
+    let original = parse_tsv_maps(&directory.read_to_string("query_from_cbor.tsv"));
+    assert_eq!(original, parse_tsv_maps(QUERY_MAP_TSV));
+    assert_eq!(
+        parse_tsv_maps(&directory.read_to_string("round_trip.tsv")),
+        original
+    );
+}
+
+#[test]
+fn convert_map_rejects_decompression_arguments() {
+    let directory = TestDirectory::with_example_maps("convert_map_rejects_decompression_arguments");
+    assert_exit_code(
+        &directory.run(&[
+            "convert-map",
+            "--from",
+            "tsv",
+            "--to",
+            "cbor",
+            "query.tsv",
+            "query.cbor",
+            "--input",
+            "input.paf",
+        ]),
+        2,
+    );
+}
+
+#[test]
+fn huge_expansion_is_refused() {
+    let directory = TestDirectory::new("huge_expansion");
+    // The second character of the query expands into a run of a hundred million characters.
+    directory.write(
+        "query.tsv",
+        "h\t0,1,100000001,100000002\nq2\t0,2,3,4,5,6,7\n",
+    );
+    directory.write("target.tsv", TARGET_MAP_TSV);
+    let second_input_line = INPUT_PAF.lines().nth(1).unwrap();
+    directory.write(
+        "input.paf",
+        format!(
+            "h\t3\t0\t3\t+\tt2\t8\t0\t3\t2\t3\t60\tcg:Z:3M\tcs:Z::1*ag:1\n{second_input_line}\n"
+        ),
+    );
+
+    let output = directory.decompress(&["--max-expanded-columns", "1000", "--on-error", "skip"]);
+    assert_exit_code(&output, 3);
+    assert!(String::from_utf8_lossy(&output.stderr).contains(
+        "difference string of query h would expand to 100000000 columns, more than the maximum of 1000"
+    ));
+    // The other line is decompressed as usual.
+    assert_eq!(
+        directory.read_to_string("output.paf"),
+        OUTPUT_PAF.lines().nth(1).unwrap().to_string() + "\n"
+    );
+}
+
+#[test]
+fn original_output_lines_up_with_output() {
+    let directory = TestDirectory::with_example_maps("original_output");
+    directory.write("input.paf", format!("# comment\n{INPUT_PAF}"));
+    // The first alignment is split into three records.
+    assert_exit_code(
+        &directory.decompress(&[
+            "--emit-original",
+            "--original-output",
+            "original.paf",
+            "--passthrough-comments",
+            "--split-on-gap",
+            "2",
+        ]),
+        0,
+    );
+
+    let output = directory.read_to_string("output.paf");
+    let original = directory.read_to_string("original.paf");
+    let output_lines: Vec<_> = output.lines().collect();
+    let original_lines: Vec<_> = original.lines().collect();
+    assert_eq!(output_lines.len(), 5);
+    assert_eq!(original_lines.len(), output_lines.len());
+    for (output_line, original_line) in output_lines.into_iter().zip(original_lines) {
+        if output_line.starts_with('#') {
+            assert_eq!(output_line, original_line);
+        } else {
+            let query_sequence_name = output_line.split('\t').next().unwrap();
+            let input_line = INPUT_PAF
+                .lines()
+                .find(|line| line.starts_with(&format!("{query_sequence_name}\t")))
+                .unwrap();
+            assert_eq!(original_line, input_line);
+        }
+    }
+}
+
+#[test]
+fn missing_alignment_string() {
+    let directory = TestDirectory::with_example_maps("missing_alignment_string");
+    directory.write(
+        "input.paf",
+        "q2\t6\t1\t5\t-\tt2\t8\t2\t6\t4\t4\t30\ttp:A:P\n",
+    );
+
+    let output = directory.decompress(&[]);
+    assert_exit_code(&output, 0);
+    assert!(String::from_utf8_lossy(&output.stderr).contains(
+        "Alignment of query q2 has neither a CIGAR string nor a difference string, its base counts are output in compressed space"
+    ));
+    // The base counts are output unchanged.
+    assert_eq!(
+        directory.read_to_string("output.paf"),
+        "q2\t7\t2\t6\t-\tt2\t8\t2\t6\t4\t4\t30\ttp:A:P\n"
+    );
+
+    let output = directory.decompress(&["--require-alignment-string"]);
+    assert_exit_code(&output, 4);
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("alignment of query q2 has neither a CIGAR string nor a difference string"));
+}
+
+#[test]
+fn annotate_decompression() {
+    let directory = TestDirectory::with_example_maps("annotate_decompression");
+    directory.write("input.paf", INPUT_PAF);
+
+    assert_exit_code(&directory.decompress(&["--annotate-decompression"]), 0);
+    let expected_output: String = OUTPUT_PAF
+        .lines()
+        .zip(["\thd:i:1\tqx:i:4\ttx:i:4", "\thd:i:1\tqx:i:0\ttx:i:0"])
+        .map(|(line, annotation)| format!("{line}{annotation}\n"))
+        .collect();
+    assert_eq!(directory.read_to_string("output.paf"), expected_output);
+
+    // The annotation is not removed by the tag filters.
+    assert_exit_code(
+        &directory.decompress(&["--annotate-decompression", "--keep-tags", "NM"]),
+        0,
+    );
+    let output = directory.read_to_string("output.paf");
+    let first_line_tags: Vec<_> = output
+        .lines()
+        .next()
+        .unwrap()
+        .split('\t')
+        .skip(12)
+        .collect();
+    assert_eq!(first_line_tags, ["NM:i:6", "hd:i:1", "qx:i:4", "tx:i:4"]);
+}
+
+#[test]
+fn malformed_trailing_content_is_skipped() {
+    let directory = TestDirectory::with_example_maps("malformed_trailing_content");
+    let first_input_line = INPUT_PAF.lines().next().unwrap();
+    directory.write(
+        "input.paf",
+        format!("q2\t6\t1\t5\t-\tt2\t8\t2\t6\t4\t4\t30\tcg:Z:4M\tstray\n{first_input_line}\n"),
+    );
+    let output = directory.decompress(&["--on-error", "skip"]);
+    assert_exit_code(&output, 3);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Skipping PAF line 1"));
+    assert_eq!(
+        directory.read_to_string("output.paf"),
+        OUTPUT_PAF.lines().next().unwrap().to_string() + "\n"
+    );
+}
+
+#[test]
+fn group_by_query_with_interleaved_queries() {
+    let directory = TestDirectory::with_example_maps("group_by_query_interleaved");
+    let second_input_line = INPUT_PAF.lines().nth(1).unwrap();
+    directory.write(
+        "input.paf",
+        format!(
+            "q1\t10\t5\t10\t+\tt1\t12\t0\t5\t5\t5\t60\ttp:A:P
+q1\t10\t0\t5\t+\tt1\t12\t0\t5\t5\t5\t60\ttp:A:P
+{second_input_line}
+q1\t10\t2\t4\t+\tt1\t12\t0\t2\t2\t2\t60\ttp:A:P
+"
+        ),
+    );
+
+    let output = directory.decompress(&["--group-by-query", "--compute-threads", "0"]);
+    assert_exit_code(&output, 0);
+    assert!(String::from_utf8_lossy(&output.stderr).contains(
+        "Input is not sorted by query: the query sorts before query q2 of the preceding alignment, so its alignments may be output in multiple groups: line 4, query q1"
+    ));
+    let output = directory.read_to_string("output.paf");
+    let query_ranges: Vec<_> = output
+        .lines()
+        .map(|line| line.split('\t').take(4).collect::<Vec<_>>().join("\t"))
+        .collect();
+    // The first group of q1 is sorted by decompressed query start, the second group is output separately.
+    assert_eq!(
+        query_ranges,
+        [
+            "q1\t14\t0\t8",
+            "q1\t14\t8\t14",
+            "q2\t7\t2\t6",
+            "q1\t14\t3\t5"
+        ]
+    );
+}
+
+#[test]
+fn remap_custom_tag() {
+    let directory = TestDirectory::with_example_maps("remap_custom_tag");
+    directory.write("input.paf", INPUT_PAF.lines().next().unwrap());
+    assert_exit_code(&directory.decompress(&["--remap-tag", "zz:query"]), 0);
+    assert_eq!(
+        directory.read_to_string("output.paf"),
+        OUTPUT_PAF
+            .lines()
+            .next()
+            .unwrap()
+            .replace("zz:i:5", "zz:i:8")
+            + "\n"
+    );
+}
+
+#[test]
+fn count_only_logs_the_same_statistics() {
+    let directory = TestDirectory::with_example_maps("count_only");
+    directory.write("input.paf", INPUT_PAF.repeat(3));
+    let statistics = |stderr: &[u8]| -> Vec<String> {
+        String::from_utf8_lossy(stderr)
+            .lines()
+            .filter(|line| line.contains("Decompressed") || line.contains("expanded from"))
+            .map(|line| line.split_once("[INFO]").unwrap().1.to_string())
+            .collect()
+    };
+
+    let output = directory.decompress(&[]);
+    assert_exit_code(&output, 0);
+    let decompression_statistics = statistics(&output.stderr);
+    assert_eq!(decompression_statistics.len(), 3);
+
+    let output = directory.run(&[
+        "--input",
+        "input.paf",
+        "--count-only",
+        "--query-hodeco-map",
+        "query.tsv",
+        "--target-hodeco-map",
+        "target.tsv",
+        "--hodeco-map-format",
+        "tsv",
+    ]);
+    assert_exit_code(&output, 0);
+    assert_eq!(statistics(&output.stderr), decompression_statistics);
+}
+
+#[test]
+fn swap_roles() {
+    // The map files are given in the wrong order.
+    let directory = TestDirectory::new("swap_roles");
+    directory.write("query.tsv", TARGET_MAP_TSV);
+    directory.write("target.tsv", QUERY_MAP_TSV);
+    directory.write("input.paf", INPUT_PAF);
+
+    let output = directory.decompress(&["--on-error", "skip"]);
+    assert_exit_code(&output, 3);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("see --swap-roles"));
+
+    let output = directory.decompress(&["--swap-roles"]);
+    assert_exit_code(&output, 0);
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("see --swap-roles"));
+    assert_eq!(directory.read_to_string("output.paf"), OUTPUT_PAF);
+}
+
+#[test]
+fn cbor_output_round_trips_to_text_output() {
+    let directory = TestDirectory::with_example_maps("cbor_output_round_trips_to_text_output");
+    directory.write("input.paf", INPUT_PAF);
+    for strand_format in ["plus-minus", "fwd-rev"] {
+        assert_exit_code(
+            &directory.decompress(&["--strand-format", strand_format]),
+            0,
+        );
+        let text_output = directory.read_to_string("output.paf");
+        assert_exit_code(
+            &directory.decompress(&["--strand-format", strand_format, "--output-format", "cbor"]),
+            0,
+        );
+        let cbor_output = directory.read("output.paf");
+        let records: Vec<_> = PafRecordReader::new(cbor_output.as_slice())
+            .map(|record| record.unwrap().to_string() + "\n")
+            .collect();
+        assert_eq!(
+            records.concat(),
+            text_output,
+            "--strand-format {strand_format}"
+        );
+    }
+}
+
+#[test]
+fn target_offset_table_keeps_target_coordinates_within_target_length() {
+    let directory = TestDirectory::with_example_maps("target_offset_table");
+    directory.write("input.paf", INPUT_PAF);
+    // Replaces the target name, length, start and end columns of the expected output.
+    let expected_output = |targets: [[&str; 4]; 2]| -> String {
+        OUTPUT_PAF
+            .lines()
+            .zip(targets)
+            .map(|(line, target)| {
+                let mut columns: Vec<_> = line.split('\t').collect();
+                columns[5..9].copy_from_slice(&target);
+                columns.join("\t") + "\n"
+            })
+            .collect()
+    };
+
+    for (table, targets) in [
+        // Without length, the target length grows by the offset.
+        (
+            "t1\t100\tref\nt2\t10\n",
+            [["ref", "116", "101", "116"], ["t2", "18", "12", "16"]],
+        ),
+        // The length of the renamed target is written as given, an empty name keeps the name.
+        (
+            "t1\t100\tref\t1000\nt2\t0\t\t8\n",
+            [["ref", "1000", "101", "116"], ["t2", "8", "2", "6"]],
+        ),
+    ] {
+        directory.write("offsets.tsv", table);
+        assert_exit_code(
+            &directory.decompress(&["--target-offset-table", "offsets.tsv"]),
+            0,
+        );
+        assert_eq!(
+            directory.read_to_string("output.paf"),
+            expected_output(targets),
+            "table {table:?}"
+        );
+    }
+
+    // The offset alignment of t1 ends at 116, beyond the given length.
+    directory.write("offsets.tsv", "t1\t100\tref\t115\nt2\t0\n");
+    let output =
+        directory.decompress(&["--target-offset-table", "offsets.tsv", "--on-error", "skip"]);
+    assert_exit_code(&output, 3);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("116"));
+    assert_eq!(
+        directory.read_to_string("output.paf"),
+        OUTPUT_PAF.lines().nth(1).unwrap().to_string() + "\n"
+    );
+
+    directory.write("offsets.tsv", "t1\t100\tref\tlong\n");
+    assert_exit_code(
+        &directory.decompress(&["--target-offset-table", "offsets.tsv"]),
+        4,
+    );
+}
+
+#[test]
+fn tiny_memory_budget_keeps_maps_in_smallest_representation() {
+    let directory = TestDirectory::with_example_maps("tiny_memory_budget");
+    directory.write("input.paf", INPUT_PAF);
+    let output = directory.decompress(&["--max-memory", "1"]);
+    assert_exit_code(&output, 0);
+    assert!(String::from_utf8_lossy(&output.stderr).contains(
+        "exceeds the memory budget of 1 bytes, keeping them in their smallest representation"
+    ));
+    assert_eq!(directory.read_to_string("output.paf"), OUTPUT_PAF);
+}
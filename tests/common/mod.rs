@@ -0,0 +1,137 @@
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+/// The query hodeco map of the example alignments in TSV format.
+pub const QUERY_MAP_TSV: &str = "q1\t0,1,3,4,5,8,9,10,12,13,14\nq2\t0,2,3,4,5,6,7\n";
+
+/// The target hodeco map of the example alignments in TSV format.
+pub const TARGET_MAP_TSV: &str = "t1\t0,1,2,4,5,6,9,10,11,13,14,15,16\nt2\t0,1,2,3,4,5,6,7,8\n";
+
+/// Two example alignments on the forward and reverse strand.
+pub const INPUT_PAF: &str = "q1\t10\t0\t10\t+\tt1\t12\t1\t12\t8\t11\t60\tNM:i:3\tcg:Z:4M1I3M2D2M\tcs:Z::2*ac:1+g:3-tt:2\tdv:f:0.1\tde:f:0.05\tzz:i:5
+q2\t6\t1\t5\t-\tt2\t8\t2\t6\t4\t4\t30\tcg:Z:4M\tcs:Z::4
+";
+
+/// The decompressed example alignments.
+pub const OUTPUT_PAF: &str = "q1\t14\t0\t14\t+\tt1\t16\t1\t16\t11\t17\t60\tNM:i:6\tde:f:0.23076923076923078\tcg:Z:5M3I4M3D2M\tcs:Z::3*ac:1+ggg:4-ttt:2\tdv:f:0.4117647058823529\tzz:i:5
+q2\t7\t2\t6\t-\tt2\t8\t2\t6\t4\t4\t30\tNM:i:0\tcg:Z:4M\tcs:Z::4
+";
+
+/// Parses a single line of PAF.
+pub fn parse_paf_line(line: &str) -> minimap2_paf_io::data::PAFLine {
+    minimap2_paf_io::input::parse_line(&mut &*line).unwrap()
+}
+
+/// Builds the offsets of a hodeco map from the decompressed lengths of its homopolymer runs.
+pub fn map_from_runs(runs: &[usize]) -> Vec<usize> {
+    let mut offsets = vec![0];
+    for run in runs {
+        offsets.push(offsets.last().unwrap() + run);
+    }
+    offsets
+}
+
+/// Parses hodeco maps in TSV format.
+pub fn parse_tsv_maps(maps: &str) -> HashMap<String, Vec<usize>> {
+    maps.lines()
+        .map(|line| {
+            let (sequence_name, offsets) = line.split_once('\t').unwrap();
+            let offsets = offsets
+                .split(',')
+                .map(|offset| offset.parse().unwrap())
+                .collect();
+            (sequence_name.to_string(), offsets)
+        })
+        .collect()
+}
+
+/// Returns the example query and target hodeco maps.
+pub fn example_maps() -> (HashMap<String, Vec<usize>>, HashMap<String, Vec<usize>>) {
+    (
+        parse_tsv_maps(QUERY_MAP_TSV),
+        parse_tsv_maps(TARGET_MAP_TSV),
+    )
+}
+
+/// A directory for the files of a single test, which is emptied when the test starts.
+pub struct TestDirectory {
+    path: PathBuf,
+}
+
+impl TestDirectory {
+    pub fn new(name: &str) -> Self {
+        let path = Path::new(env!("CARGO_TARGET_TMPDIR")).join(name);
+        if path.exists() {
+            fs::remove_dir_all(&path).unwrap();
+        }
+        fs::create_dir_all(&path).unwrap();
+        Self { path }
+    }
+
+    /// Creates a test directory with the example hodeco maps as `query.tsv` and `target.tsv`.
+    pub fn with_example_maps(name: &str) -> Self {
+        let directory = Self::new(name);
+        directory.write("query.tsv", QUERY_MAP_TSV);
+        directory.write("target.tsv", TARGET_MAP_TSV);
+        directory
+    }
+
+    pub fn path(&self, file_name: &str) -> PathBuf {
+        self.path.join(file_name)
+    }
+
+    pub fn write(&self, file_name: &str, contents: impl AsRef<[u8]>) -> PathBuf {
+        let path = self.path(file_name);
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    pub fn read(&self, file_name: &str) -> Vec<u8> {
+        fs::read(self.path(file_name)).unwrap()
+    }
+
+    pub fn read_to_string(&self, file_name: &str) -> String {
+        fs::read_to_string(self.path(file_name)).unwrap()
+    }
+
+    /// Runs the binary with the given arguments in this directory.
+    pub fn run(&self, arguments: &[&str]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_minimap2-homopolymer-decompression"))
+            .current_dir(&self.path)
+            .args(arguments)
+            .output()
+            .unwrap()
+    }
+
+    /// Decompresses `input.paf` into `output.paf` with the example maps and the given additional arguments.
+    pub fn decompress(&self, arguments: &[&str]) -> Output {
+        let mut all_arguments = vec![
+            "--input",
+            "input.paf",
+            "--output",
+            "output.paf",
+            "--query-hodeco-map",
+            "query.tsv",
+            "--target-hodeco-map",
+            "target.tsv",
+            "--hodeco-map-format",
+            "tsv",
+        ];
+        all_arguments.extend_from_slice(arguments);
+        self.run(&all_arguments)
+    }
+}
+
+/// Asserts that the binary exited with the given exit code, and prints its stderr otherwise.
+pub fn assert_exit_code(output: &Output, exit_code: i32) {
+    assert_eq!(
+        output.status.code(),
+        Some(exit_code),
+        "stderr:\n{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
@@ -0,0 +1,91 @@
+mod common;
+
+use common::{map_from_runs, parse_paf_line, INPUT_PAF};
+use minimap2_homopolymer_decompression::{
+    compress_coordinate, hodeco_paf_line, CoordinateMap, HodecoMap, MapOffsetWidth,
+};
+use std::collections::HashMap;
+
+/// A map that does not change any coordinates.
+struct Identity {
+    length: usize,
+}
+
+impl CoordinateMap for Identity {
+    fn expand(&self, compressed: usize) -> usize {
+        compressed
+    }
+
+    fn total_expanded(&self) -> usize {
+        self.length
+    }
+
+    fn total_compressed(&self) -> usize {
+        self.length
+    }
+}
+
+#[test]
+fn custom_identity_map_keeps_alignment() {
+    let query_maps = HashMap::from([("q1".to_string(), Identity { length: 10 })]);
+    let target_maps = HashMap::from([("t1".to_string(), Identity { length: 12 })]);
+    let paf_line = parse_paf_line(INPUT_PAF.lines().next().unwrap());
+    let hodeco_paf = hodeco_paf_line(paf_line.clone(), &query_maps, &target_maps).unwrap();
+
+    assert_eq!(
+        (
+            hodeco_paf.query_sequence_length,
+            hodeco_paf.query_start_coordinate,
+            hodeco_paf.query_end_coordinate,
+            hodeco_paf.target_sequence_length,
+            hodeco_paf.target_start_coordinate_on_original_strand,
+            hodeco_paf.target_end_coordinate_on_original_strand,
+        ),
+        (10, 0, 10, 12, 1, 12)
+    );
+    assert_eq!(hodeco_paf.cigar_string, paf_line.cigar_string);
+    assert_eq!(hodeco_paf.difference_string, paf_line.difference_string);
+    // The base counts are recomputed from the CIGAR string `4M1I3M2D2M`.
+    assert_eq!(
+        (
+            hodeco_paf.number_of_matching_bases,
+            hodeco_paf.number_of_bases_and_gaps,
+        ),
+        (9, 12)
+    );
+}
+
+#[test]
+fn compress_coordinate_at_run_starts_interiors_and_boundaries() {
+    // Runs of lengths 2, 0, 3 and 1.
+    let map = map_from_runs(&[2, 0, 3, 1]);
+    assert_eq!(map, [0, 2, 2, 5, 6]);
+    let compressed: Vec<_> = (0..=8)
+        .map(|decompressed| compress_coordinate(&map, decompressed))
+        .collect();
+    // The boundary 2 belongs to the run starting there, skipping the empty run 1,
+    // the decompressed length 6 maps to the compressed length 4, and larger coordinates are clamped.
+    assert_eq!(compressed, [0, 0, 2, 2, 2, 3, 4, 4, 4]);
+    assert_eq!(compress_coordinate(&map, usize::MAX), 4);
+
+    for compressed in 0..=map.total_compressed() {
+        let is_empty_run = compressed < map.total_compressed()
+            && map.expand(compressed + 1) == map.expand(compressed);
+        assert_eq!(
+            compress_coordinate(&map, map.expand(compressed)) == compressed,
+            !is_empty_run,
+            "compressed coordinate {compressed}"
+        );
+    }
+}
+
+#[test]
+fn narrow_offsets_are_checked_individually() {
+    // Not monotonic, so the last offset is not the largest.
+    let offsets = vec![0, 1 << 32, 3];
+    assert!(HodecoMap::from_offsets(offsets.clone(), MapOffsetWidth::Bits32).is_none());
+    assert!(matches!(
+        HodecoMap::from_offsets(offsets, MapOffsetWidth::Auto),
+        Some(HodecoMap::Wide(_))
+    ));
+}
@@ -0,0 +1,36 @@
+mod common;
+
+use common::{example_maps, parse_paf_line, INPUT_PAF};
+use minimap2_homopolymer_decompression::{hodeco_coordinate_tag, HodecoError, SequenceRole};
+
+#[test]
+fn custom_tag_is_remapped_through_the_map_of_its_role() {
+    let (query_maps, target_maps) = example_maps();
+    for (role, expected_field) in [
+        (SequenceRole::Query, "zz:i:8"),
+        (SequenceRole::Target, "zz:i:6"),
+    ] {
+        let mut paf_line = parse_paf_line(INPUT_PAF.lines().next().unwrap());
+        hodeco_coordinate_tag(&mut paf_line, "zz", role, &query_maps, &target_maps).unwrap();
+        assert_eq!(paf_line.unknown_fields, [expected_field]);
+    }
+}
+
+#[test]
+fn missing_or_invalid_custom_tag_is_an_error() {
+    let (query_maps, target_maps) = example_maps();
+    let first_input_line = INPUT_PAF.lines().next().unwrap();
+
+    let mut paf_line = parse_paf_line(first_input_line);
+    assert!(matches!(
+        hodeco_coordinate_tag(&mut paf_line, "zs", SequenceRole::Query, &query_maps, &target_maps),
+        Err(HodecoError::MissingTag { tag, .. }) if tag == "zs"
+    ));
+
+    // The compressed length of q1 is 10.
+    let mut paf_line = parse_paf_line(&first_input_line.replace("zz:i:5", "zz:i:11"));
+    assert!(matches!(
+        hodeco_coordinate_tag(&mut paf_line, "zz", SequenceRole::Query, &query_maps, &target_maps),
+        Err(HodecoError::InvalidCoordinateTag { field, .. }) if field == "zz:i:11"
+    ));
+}
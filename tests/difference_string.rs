@@ -0,0 +1,148 @@
+mod common;
+
+use common::{example_maps, map_from_runs, parse_paf_line, INPUT_PAF, OUTPUT_PAF};
+use minimap2_homopolymer_decompression::{
+    check_difference_string_consistency, check_difference_string_roundtrip, hodeco_paf_line,
+    HodecoError,
+};
+use minimap2_paf_io::data::DifferenceColumn;
+use std::collections::HashMap;
+
+#[test]
+fn difference_string_agrees_with_query_range() {
+    let (query_maps, target_maps) = example_maps();
+    for line in INPUT_PAF.lines() {
+        let hodeco_paf = hodeco_paf_line(parse_paf_line(line), &query_maps, &target_maps).unwrap();
+        check_difference_string_consistency(&hodeco_paf).unwrap();
+    }
+}
+
+#[test]
+fn difference_string_disagrees_with_query_range() {
+    // The query range of the first line is one longer than its difference string.
+    let hodeco_paf = parse_paf_line(
+        &OUTPUT_PAF
+            .lines()
+            .next()
+            .unwrap()
+            .replace("q1\t14\t0\t14\t", "q1\t15\t0\t15\t"),
+    );
+    assert!(matches!(
+        check_difference_string_consistency(&hodeco_paf),
+        Err(HodecoError::DifferenceStringLengthMismatch {
+            query_sequence_name,
+            difference_string_length: 14,
+            coordinate_length: 15,
+        }) if query_sequence_name == "q1"
+    ));
+}
+
+#[test]
+fn non_ascii_character_is_rejected() {
+    let (query_maps, target_maps) = example_maps();
+    let first_input_line = INPUT_PAF.lines().next().unwrap();
+    for (column, replacement) in [("*ac", "*aé"), ("+g", "+ä"), ("-tt", "-tß")] {
+        let paf_line = parse_paf_line(&first_input_line.replace(column, replacement));
+        let invalid_character = replacement.chars().last().unwrap();
+        assert!(matches!(
+            hodeco_paf_line(paf_line, &query_maps, &target_maps),
+            Err(HodecoError::InvalidDifferenceCharacter {
+                query_sequence_name,
+                character,
+            }) if query_sequence_name == "q1" && character == invalid_character
+        ));
+    }
+}
+
+/// An alignment with adjacent mismatches and indels.
+const ADJACENT_DIFFERENCES_PAF: &str =
+    "q\t7\t0\t7\t+\tt\t7\t0\t7\t2\t9\t60\tcg:Z:3M2I2D2M\tcs:Z::1*ac*gt+ac-tg*ca:1";
+
+/// Returns the hodeco maps of [ADJACENT_DIFFERENCES_PAF],
+/// whose aligned bases have runs of the same length in the query and the target.
+fn adjacent_differences_maps() -> (HashMap<String, Vec<usize>>, HashMap<String, Vec<usize>>) {
+    (
+        [("q".to_string(), map_from_runs(&[2, 3, 2, 1, 3, 2, 1]))].into(),
+        [("t".to_string(), map_from_runs(&[2, 3, 2, 4, 1, 2, 1]))].into(),
+    )
+}
+
+#[test]
+fn difference_string_roundtrips_with_adjacent_mismatches_and_indels() {
+    let (query_maps, target_maps) = example_maps();
+    for line in INPUT_PAF.lines() {
+        let paf_line = parse_paf_line(line);
+        let hoco_difference_string = paf_line.difference_string.clone();
+        let hodeco_paf = hodeco_paf_line(paf_line, &query_maps, &target_maps).unwrap();
+        check_difference_string_roundtrip(
+            hoco_difference_string.as_ref(),
+            &hodeco_paf,
+            &query_maps,
+            &target_maps,
+        )
+        .unwrap();
+    }
+
+    let (query_maps, target_maps) = adjacent_differences_maps();
+    let paf_line = parse_paf_line(ADJACENT_DIFFERENCES_PAF);
+    let hoco_difference_string = paf_line.difference_string.clone();
+    let hodeco_paf = hodeco_paf_line(paf_line, &query_maps, &target_maps).unwrap();
+    assert_eq!(
+        hodeco_paf.difference_string,
+        parse_paf_line(
+            "q\t14\t0\t14\t+\tt\t15\t0\t15\t3\t19\t60\tcs:Z::2*ac*ac*ac*gt*gt+accc-ttttg*ca*ca:1"
+        )
+        .difference_string
+    );
+    check_difference_string_roundtrip(
+        hoco_difference_string.as_ref(),
+        &hodeco_paf,
+        &query_maps,
+        &target_maps,
+    )
+    .unwrap();
+}
+
+#[test]
+fn difference_string_roundtrip_detects_wrong_expansion() {
+    let (query_maps, target_maps) = adjacent_differences_maps();
+    let paf_line = parse_paf_line(ADJACENT_DIFFERENCES_PAF);
+    let hoco_difference_string = paf_line.difference_string.clone();
+    let hodeco_paf = hodeco_paf_line(paf_line, &query_maps, &target_maps).unwrap();
+
+    // Each modification breaks the expansion of the compressed column with the given index.
+    type Modification = fn(&mut Vec<DifferenceColumn>);
+    let modifications: [(usize, Modification); 3] = [
+        // One copy of the first mismatch is missing.
+        (1, |columns| {
+            columns.remove(1);
+        }),
+        // The adjacent mismatches are swapped.
+        (1, |columns| columns.swap(3, 4)),
+        // The insertion is one character short.
+        (3, |columns| {
+            columns[6] = DifferenceColumn::Insertion {
+                superfluous_query_characters: "acc".to_string(),
+            }
+        }),
+    ];
+    for (expected_column_index, modify) in modifications {
+        let mut modified_paf = hodeco_paf.clone();
+        modify(&mut modified_paf.difference_string.as_mut().unwrap().0);
+        assert!(
+            matches!(
+                check_difference_string_roundtrip(
+                    hoco_difference_string.as_ref(),
+                    &modified_paf,
+                    &query_maps,
+                    &target_maps,
+                ),
+                Err(HodecoError::DifferenceStringRoundtripMismatch {
+                    query_sequence_name,
+                    column_index,
+                }) if query_sequence_name == "q" && column_index == expected_column_index
+            ),
+            "expected a mismatch at column {expected_column_index}"
+        );
+    }
+}
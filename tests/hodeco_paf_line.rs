@@ -0,0 +1,262 @@
+mod common;
+
+use common::{example_maps, map_from_runs, parse_paf_line, INPUT_PAF};
+use minimap2_homopolymer_decompression::{
+    compute_decompressed_stats, hodeco_paf_line, AlignmentStats, HodecoError, SequenceRole,
+};
+use minimap2_paf_io::data::{AlignmentDifference, DifferenceColumn, PAFLine};
+use std::collections::HashMap;
+
+fn example_line(index: usize) -> PAFLine {
+    parse_paf_line(INPUT_PAF.lines().nth(index).unwrap())
+}
+
+#[test]
+fn empty_indel_is_rejected() {
+    let (query_maps, target_maps) = example_maps();
+    for empty_indel in [
+        DifferenceColumn::Insertion {
+            superfluous_query_characters: String::new(),
+        },
+        DifferenceColumn::Deletion {
+            missing_query_characters: String::new(),
+        },
+    ] {
+        let mut paf_line = example_line(1);
+        paf_line.difference_string = Some(AlignmentDifference(vec![
+            DifferenceColumn::Match { length: 2 },
+            empty_indel,
+            DifferenceColumn::Match { length: 2 },
+        ]));
+        assert!(matches!(
+            hodeco_paf_line(paf_line, &query_maps, &target_maps),
+            Err(HodecoError::EmptyIndel { query_sequence_name }) if query_sequence_name == "q2"
+        ));
+    }
+}
+
+#[test]
+fn decompressed_stats_agree_with_decompressed_line() {
+    let (query_maps, target_maps) = example_maps();
+    for index in 0..2 {
+        let paf_line = example_line(index);
+        let alignment_stats =
+            compute_decompressed_stats(&paf_line, &query_maps, &target_maps).unwrap();
+        let hodeco_paf = hodeco_paf_line(paf_line, &query_maps, &target_maps).unwrap();
+        assert_eq!(
+            alignment_stats,
+            AlignmentStats {
+                number_of_matching_bases: Some(hodeco_paf.number_of_matching_bases),
+                number_of_bases_and_gaps: Some(hodeco_paf.number_of_bases_and_gaps),
+                total_number_of_mismatches_and_gaps: hodeco_paf.total_number_of_mismatches_and_gaps,
+            }
+        );
+    }
+}
+
+#[test]
+fn decompressed_stats_reject_sequence_length_mismatch() {
+    let (query_maps, target_maps) = example_maps();
+    let mut paf_line = example_line(0);
+    paf_line.query_sequence_length += 1;
+    assert!(matches!(
+        compute_decompressed_stats(&paf_line, &query_maps, &target_maps),
+        Err(HodecoError::SequenceLengthMismatch { .. })
+    ));
+    assert!(matches!(
+        hodeco_paf_line(paf_line, &query_maps, &target_maps),
+        Err(HodecoError::SequenceLengthMismatch { .. })
+    ));
+}
+
+fn single_maps(
+    query_map: Vec<usize>,
+    target_map: Vec<usize>,
+) -> (HashMap<String, Vec<usize>>, HashMap<String, Vec<usize>>) {
+    (
+        HashMap::from([("q".to_string(), query_map)]),
+        HashMap::from([("t".to_string(), target_map)]),
+    )
+}
+
+#[test]
+fn decompressed_coordinates_within_bounds() {
+    let (query_maps, target_maps) = single_maps(vec![0, 1, 2], vec![0, 2, 3]);
+    let paf_line = parse_paf_line("q\t2\t0\t2\t+\tt\t2\t0\t2\t2\t2\t60\tcg:Z:2M");
+    let hodeco_paf = hodeco_paf_line(paf_line, &query_maps, &target_maps).unwrap();
+    assert_eq!(
+        (
+            hodeco_paf.target_start_coordinate_on_original_strand,
+            hodeco_paf.target_end_coordinate_on_original_strand,
+            hodeco_paf.target_sequence_length,
+        ),
+        (0, 3, 3)
+    );
+}
+
+#[test]
+fn decompressed_coordinates_out_of_bounds() {
+    // A corrupt map that is not monotonic, so the compressed coordinate 1 expands beyond the decompressed length 3.
+    let (query_maps, target_maps) = single_maps(vec![0, 1, 2], vec![0, 5, 3]);
+    let paf_line = parse_paf_line("q\t2\t0\t1\t+\tt\t2\t0\t1\t1\t1\t60\tcg:Z:1M");
+    for result in [
+        compute_decompressed_stats(&paf_line, &query_maps, &target_maps).map(|_| ()),
+        hodeco_paf_line(paf_line, &query_maps, &target_maps).map(|_| ()),
+    ] {
+        assert!(matches!(
+            result,
+            Err(HodecoError::CoordinateOutOfBounds {
+                role: SequenceRole::Target,
+                sequence_name,
+                coordinate: 5,
+                decompressed_length: 3,
+            }) if sequence_name == "t"
+        ));
+    }
+}
+
+#[test]
+fn empty_alignments_are_rejected() {
+    // The query run 1 is empty, so the compressed query range 1..2 decompresses to an empty range.
+    let (query_maps, target_maps) = single_maps(vec![0, 1, 1, 2], vec![0, 1, 2, 3]);
+    for (paf_line, expected_role) in [
+        (
+            "q\t3\t1\t2\t+\tt\t3\t1\t2\t1\t1\t60\tcg:Z:1M",
+            SequenceRole::Query,
+        ),
+        (
+            "q\t3\t0\t2\t+\tt\t3\t2\t2\t0\t0\t60\tcg:Z:1M",
+            SequenceRole::Target,
+        ),
+    ] {
+        let paf_line = parse_paf_line(paf_line);
+        let is_empty_alignment = |result: Result<(), HodecoError>| {
+            matches!(
+                result,
+                Err(HodecoError::EmptyAlignment { role, .. }) if role == expected_role
+            )
+        };
+        assert!(is_empty_alignment(
+            compute_decompressed_stats(&paf_line, &query_maps, &target_maps).map(|_| ())
+        ));
+        assert!(is_empty_alignment(
+            hodeco_paf_line(paf_line, &query_maps, &target_maps).map(|_| ())
+        ));
+    }
+}
+
+#[test]
+fn mismatch_on_empty_run_is_rejected() {
+    let (query_maps, target_maps) = single_maps(vec![0, 1, 3, 3, 4, 5], vec![0, 1, 2, 3, 4, 5]);
+    let paf_line = parse_paf_line("q\t5\t0\t5\t+\tt\t5\t0\t5\t4\t5\t60\tcg:Z:5M\tcs:Z::2*ag:2");
+    for result in [
+        compute_decompressed_stats(&paf_line, &query_maps, &target_maps).map(|_| ()),
+        hodeco_paf_line(paf_line, &query_maps, &target_maps).map(|_| ()),
+    ] {
+        assert!(matches!(
+            result,
+            Err(HodecoError::EmptyRun {
+                role: SequenceRole::Query,
+                sequence_name,
+                position: 2,
+            }) if sequence_name == "q"
+        ));
+    }
+}
+
+#[test]
+fn compressed_coordinates_at_sequence_end_are_accepted() {
+    let (query_maps, target_maps) = single_maps(vec![0, 1, 3], vec![0, 2, 3]);
+    let paf_line = parse_paf_line("q\t2\t1\t2\t+\tt\t2\t1\t2\t1\t1\t60\tcg:Z:1M");
+    let stats = compute_decompressed_stats(&paf_line, &query_maps, &target_maps).unwrap();
+    assert_eq!(stats.number_of_bases_and_gaps, Some(2));
+    let hodeco_paf = hodeco_paf_line(paf_line, &query_maps, &target_maps).unwrap();
+    assert_eq!(
+        (
+            hodeco_paf.query_start_coordinate,
+            hodeco_paf.query_end_coordinate,
+            hodeco_paf.target_start_coordinate_on_original_strand,
+            hodeco_paf.target_end_coordinate_on_original_strand,
+        ),
+        (1, 3, 2, 3)
+    );
+}
+
+#[test]
+fn compressed_coordinates_past_sequence_end_are_rejected() {
+    let (query_maps, target_maps) = single_maps(vec![0, 1, 3], vec![0, 2, 3]);
+    for (paf_line, expected_role, expected_end) in [
+        (
+            "q\t2\t1\t3\t+\tt\t2\t0\t2\t2\t2\t60\tcg:Z:2M",
+            SequenceRole::Query,
+            3,
+        ),
+        (
+            "q\t2\t0\t2\t+\tt\t2\t1\t3\t2\t2\t60\tcg:Z:2M",
+            SequenceRole::Target,
+            3,
+        ),
+    ] {
+        let paf_line = parse_paf_line(paf_line);
+        let is_out_of_range = |result: Result<_, HodecoError>| {
+            matches!(
+                result,
+                Err(HodecoError::CoordinateOutOfRange {
+                    role,
+                    start: 1,
+                    end,
+                    compressed_length: 2,
+                    ..
+                }) if role == expected_role && end == expected_end
+            )
+        };
+        assert!(is_out_of_range(
+            compute_decompressed_stats(&paf_line, &query_maps, &target_maps).map(|_| ())
+        ));
+        assert!(is_out_of_range(
+            hodeco_paf_line(paf_line, &query_maps, &target_maps).map(|_| ())
+        ));
+    }
+}
+
+#[test]
+fn divergences_are_recomputed_independently_on_gap_rich_alignment() {
+    // The inserted query characters and the deleted target characters expand into longer runs than the matches.
+    let (query_maps, target_maps) = single_maps(
+        map_from_runs(&[1, 1, 3, 3, 1, 1]),
+        map_from_runs(&[1, 1, 2, 2, 1, 1]),
+    );
+    let paf_line = parse_paf_line(
+        "q\t6\t0\t6\t+\tt\t6\t0\t6\t4\t8\t60\tcg:Z:2M2I2D2M\tcs:Z::2+aa-cc:2\tdv:f:0.5\tde:f:0.25",
+    );
+    let hodeco_paf = hodeco_paf_line(paf_line, &query_maps, &target_maps).unwrap();
+    assert_eq!(
+        hodeco_paf.difference_string,
+        parse_paf_line("q\t10\t0\t10\t+\tt\t8\t0\t8\t4\t14\t60\tcs:Z::2+aaaaaa-cccc:2")
+            .difference_string
+    );
+    // Ten gap characters among fourteen columns, but only two gap events among six events.
+    let approximate_divergence = hodeco_paf.approximate_per_base_sequence_divergence.unwrap();
+    let gap_compressed_divergence = hodeco_paf
+        .gap_compressed_per_base_sequence_divergence
+        .unwrap();
+    assert_eq!(approximate_divergence, 10.0 / 14.0);
+    assert_eq!(gap_compressed_divergence, 2.0 / 6.0);
+}
+
+#[test]
+fn divergences_without_difference_string_are_rescaled_by_query_expansion() {
+    let (query_maps, target_maps) = example_maps();
+    let mut paf_line = example_line(0);
+    paf_line.difference_string = None;
+    let hodeco_paf = hodeco_paf_line(paf_line, &query_maps, &target_maps).unwrap();
+    // The ratio uses the compressed query length 10 from before the line was decompressed.
+    assert_eq!(
+        hodeco_paf.approximate_per_base_sequence_divergence,
+        Some(0.1 * (14.0 / 10.0))
+    );
+    assert_eq!(
+        hodeco_paf.gap_compressed_per_base_sequence_divergence,
+        Some(0.05 * (14.0 / 10.0))
+    );
+}
@@ -0,0 +1,31 @@
+mod common;
+
+use common::{parse_paf_line, INPUT_PAF};
+use minimap2_homopolymer_decompression::{PafRecord, PafRecordReader};
+
+#[test]
+fn record_from_paf_line_formats_like_paf_line() {
+    let lines = INPUT_PAF.lines().chain([
+        "q\t10\t0\t10\t+\tt\t10\t0\t10\t10\t10\t60\tNM:i:0\tms:i:20\tAS:i:20\tnn:i:0\ttp:A:P\tcm:i:2\ts1:i:9\ts2:i:0\tde:f:0\trl:i:0\tMD:Z:10\tSA:Z:t,1,+,10M,60,0;\tts:A:+\tcg:Z:10M\tcs:Z::10\tdv:f:0\txx:Z:unknown",
+    ]);
+    for line in lines {
+        let paf_line = parse_paf_line(line);
+        let strand = if paf_line.strand { "+" } else { "-" };
+        let record = PafRecord::from_paf_line(&paf_line, strand);
+        assert_eq!(record.to_string(), paf_line.to_string());
+        assert_eq!(PafRecord::from_line(&paf_line.to_string()), Some(record));
+    }
+}
+
+#[test]
+fn record_round_trips_through_cbor() {
+    let records: Vec<_> = INPUT_PAF
+        .lines()
+        .map(|line| PafRecord::from_paf_line(&parse_paf_line(line), "fwd"))
+        .collect();
+    let cbor: Vec<u8> = records.iter().flat_map(PafRecord::to_cbor).collect();
+    let decoded: Vec<_> = PafRecordReader::new(cbor.as_slice())
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(decoded, records);
+}
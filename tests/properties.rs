@@ -0,0 +1,241 @@
+mod common;
+
+use common::{map_from_runs, parse_paf_line};
+use minimap2_homopolymer_decompression::{compute_decompressed_stats, hodeco_paf_line};
+use minimap2_paf_io::data::{CigarColumn, DifferenceColumn, PAFLine};
+use proptest::collection::vec;
+use proptest::prelude::*;
+use std::collections::HashMap;
+
+/// An operation of a compressed alignment, from which both its CIGAR string and its difference string are built.
+#[derive(Clone, Debug)]
+enum Operation {
+    Match(usize),
+    Mismatch,
+    Insertion(String),
+    Deletion(String),
+}
+
+/// A compressed base of the query or the target, or an aligned pair of both.
+///
+/// Decompression assumes that aligned bases belong to homopolymer runs of the same length,
+/// so they share their run length.
+#[derive(Clone, Copy, Debug)]
+enum Base {
+    Aligned,
+    Query,
+    Target,
+}
+
+/// A compressed alignment together with the homopolymer runs of its query and target.
+#[derive(Clone, Debug)]
+struct Alignment {
+    paf_line: PAFLine,
+    query_runs: Vec<usize>,
+    target_runs: Vec<usize>,
+}
+
+fn operation() -> impl Strategy<Value = Operation> {
+    prop_oneof![
+        (1..5usize).prop_map(Operation::Match),
+        Just(Operation::Mismatch),
+        "[acgt]{1,3}".prop_map(Operation::Insertion),
+        "[acgt]{1,3}".prop_map(Operation::Deletion),
+    ]
+}
+
+/// Appends a CIGAR column, merging it into the previous column if they have the same type.
+fn push_cigar_column(cigar: &mut Vec<(usize, char)>, length: usize, kind: char) {
+    match cigar.last_mut() {
+        Some((last_length, last_kind)) if *last_kind == kind => *last_length += length,
+        _ => cigar.push((length, kind)),
+    }
+}
+
+/// Builds the PAF line of an alignment that starts with a match,
+/// with the given numbers of unaligned query and target bases before and after it.
+/// Returns the line together with all compressed bases of its query and target in order.
+fn alignment_line(
+    leading: (usize, usize),
+    first_match: usize,
+    operations: &[Operation],
+    trailing: (usize, usize),
+) -> (String, Vec<Base>) {
+    let mut bases = vec![Base::Query; leading.0];
+    bases.extend(vec![Base::Target; leading.1]);
+    bases.extend(vec![Base::Aligned; first_match]);
+    let mut cigar = Vec::new();
+    push_cigar_column(&mut cigar, first_match, 'M');
+    let mut difference_string = format!(":{first_match}");
+    let mut matches = first_match;
+    for operation in operations {
+        match operation {
+            Operation::Match(length) => {
+                push_cigar_column(&mut cigar, *length, 'M');
+                difference_string += &format!(":{length}");
+                bases.extend(vec![Base::Aligned; *length]);
+                matches += length;
+            }
+            Operation::Mismatch => {
+                push_cigar_column(&mut cigar, 1, 'M');
+                difference_string += "*ac";
+                bases.push(Base::Aligned);
+            }
+            Operation::Insertion(characters) => {
+                push_cigar_column(&mut cigar, characters.len(), 'I');
+                difference_string += &format!("+{characters}");
+                bases.extend(vec![Base::Query; characters.len()]);
+            }
+            Operation::Deletion(characters) => {
+                push_cigar_column(&mut cigar, characters.len(), 'D');
+                difference_string += &format!("-{characters}");
+                bases.extend(vec![Base::Target; characters.len()]);
+            }
+        }
+    }
+    bases.extend(vec![Base::Query; trailing.0]);
+    bases.extend(vec![Base::Target; trailing.1]);
+
+    let count = |kinds: &[char]| -> usize {
+        cigar
+            .iter()
+            .filter(|(_, kind)| kinds.contains(kind))
+            .map(|(length, _)| length)
+            .sum()
+    };
+    let (query_length, target_length) = (count(&['M', 'I']), count(&['M', 'D']));
+    let columns = count(&['M', 'I', 'D']);
+    let cigar: String = cigar
+        .iter()
+        .map(|(length, kind)| format!("{length}{kind}"))
+        .collect();
+    let line = format!(
+        "q\t{}\t{}\t{}\t+\tt\t{}\t{}\t{}\t{matches}\t{columns}\t60\tcg:Z:{cigar}\tcs:Z:{difference_string}",
+        leading.0 + query_length + trailing.0,
+        leading.0,
+        leading.0 + query_length,
+        leading.1 + target_length + trailing.1,
+        leading.1,
+        leading.1 + target_length,
+    );
+    (line, bases)
+}
+
+/// Generates compressed alignments with valid hodeco maps, whose runs decompress to between one and four bases.
+fn alignment() -> impl Strategy<Value = Alignment> {
+    (
+        (0..4usize, 0..4usize),
+        1..5usize,
+        vec(operation(), 0..12),
+        (0..4usize, 0..4usize),
+    )
+        .prop_flat_map(|(leading, first_match, operations, trailing)| {
+            let (line, bases) = alignment_line(leading, first_match, &operations, trailing);
+            let paf_line = parse_paf_line(&line);
+            vec(1..5usize, bases.len()).prop_map(move |runs| {
+                let (mut query_runs, mut target_runs) = (Vec::new(), Vec::new());
+                for (base, run) in bases.iter().zip(runs) {
+                    match base {
+                        Base::Aligned => {
+                            query_runs.push(run);
+                            target_runs.push(run);
+                        }
+                        Base::Query => query_runs.push(run),
+                        Base::Target => target_runs.push(run),
+                    }
+                }
+                Alignment {
+                    paf_line: paf_line.clone(),
+                    query_runs,
+                    target_runs,
+                }
+            })
+        })
+}
+
+fn maps(alignment: &Alignment) -> (HashMap<String, Vec<usize>>, HashMap<String, Vec<usize>>) {
+    (
+        [("q".to_string(), map_from_runs(&alignment.query_runs))].into(),
+        [("t".to_string(), map_from_runs(&alignment.target_runs))].into(),
+    )
+}
+
+proptest! {
+    #[test]
+    fn decompressed_coordinates_are_ordered_and_within_bounds(alignment in alignment()) {
+        let (query_maps, target_maps) = maps(&alignment);
+        let hodeco_paf = hodeco_paf_line(alignment.paf_line, &query_maps, &target_maps).unwrap();
+
+        prop_assert_eq!(hodeco_paf.query_sequence_length, alignment.query_runs.iter().sum::<usize>());
+        prop_assert_eq!(hodeco_paf.target_sequence_length, alignment.target_runs.iter().sum::<usize>());
+        prop_assert!(hodeco_paf.query_start_coordinate < hodeco_paf.query_end_coordinate);
+        prop_assert!(hodeco_paf.query_end_coordinate <= hodeco_paf.query_sequence_length);
+        prop_assert!(
+            hodeco_paf.target_start_coordinate_on_original_strand
+                < hodeco_paf.target_end_coordinate_on_original_strand
+        );
+        prop_assert!(
+            hodeco_paf.target_end_coordinate_on_original_strand <= hodeco_paf.target_sequence_length
+        );
+    }
+
+    #[test]
+    fn decompressed_alignment_strings_span_the_decompressed_ranges(alignment in alignment()) {
+        let (query_maps, target_maps) = maps(&alignment);
+        let hodeco_paf = hodeco_paf_line(alignment.paf_line, &query_maps, &target_maps).unwrap();
+        let query_range = hodeco_paf.query_end_coordinate - hodeco_paf.query_start_coordinate;
+        let target_range = hodeco_paf.target_end_coordinate_on_original_strand
+            - hodeco_paf.target_start_coordinate_on_original_strand;
+
+        let (mut cigar_query_span, mut cigar_target_span) = (0, 0);
+        for cigar_column in &hodeco_paf.cigar_string.as_ref().unwrap().0 {
+            match cigar_column {
+                CigarColumn::Match(length) | CigarColumn::Mismatch(length) => {
+                    cigar_query_span += length;
+                    cigar_target_span += length;
+                }
+                CigarColumn::Insertion(length) => cigar_query_span += length,
+                CigarColumn::Deletion(length) => cigar_target_span += length,
+            }
+        }
+        prop_assert_eq!((cigar_query_span, cigar_target_span), (query_range, target_range));
+
+        let (mut difference_query_span, mut difference_target_span) = (0, 0);
+        for difference_column in &hodeco_paf.difference_string.as_ref().unwrap().0 {
+            match difference_column {
+                DifferenceColumn::Match { length } => {
+                    difference_query_span += length;
+                    difference_target_span += length;
+                }
+                DifferenceColumn::Mismatch { .. } => {
+                    difference_query_span += 1;
+                    difference_target_span += 1;
+                }
+                DifferenceColumn::Insertion {
+                    superfluous_query_characters,
+                } => difference_query_span += superfluous_query_characters.len(),
+                DifferenceColumn::Deletion {
+                    missing_query_characters,
+                } => difference_target_span += missing_query_characters.len(),
+            }
+        }
+        prop_assert_eq!(
+            (difference_query_span, difference_target_span),
+            (query_range, target_range)
+        );
+    }
+
+    #[test]
+    fn decompressed_stats_agree_with_decompressed_line(alignment in alignment()) {
+        let (query_maps, target_maps) = maps(&alignment);
+        let stats = compute_decompressed_stats(&alignment.paf_line, &query_maps, &target_maps).unwrap();
+        let hodeco_paf = hodeco_paf_line(alignment.paf_line, &query_maps, &target_maps).unwrap();
+
+        prop_assert_eq!(stats.number_of_matching_bases, Some(hodeco_paf.number_of_matching_bases));
+        prop_assert_eq!(stats.number_of_bases_and_gaps, Some(hodeco_paf.number_of_bases_and_gaps));
+        prop_assert_eq!(
+            stats.total_number_of_mismatches_and_gaps,
+            hodeco_paf.total_number_of_mismatches_and_gaps
+        );
+    }
+}
@@ -0,0 +1,138 @@
+mod common;
+
+use common::{map_from_runs, parse_paf_line};
+use minimap2_homopolymer_decompression::{hodeco_paf_line, split_on_gaps};
+use std::collections::HashMap;
+
+/// Returns the coordinates, statistics, CIGAR string and difference string of a line.
+fn summary(
+    paf_line: &minimap2_paf_io::data::PAFLine,
+) -> (
+    usize,
+    usize,
+    usize,
+    usize,
+    usize,
+    usize,
+    Option<usize>,
+    String,
+) {
+    let line = paf_line.to_string();
+    let optional_fields: Vec<_> = line.split('\t').skip(12).collect();
+    (
+        paf_line.query_start_coordinate,
+        paf_line.query_end_coordinate,
+        paf_line.target_start_coordinate_on_original_strand,
+        paf_line.target_end_coordinate_on_original_strand,
+        paf_line.number_of_matching_bases,
+        paf_line.number_of_bases_and_gaps,
+        paf_line.total_number_of_mismatches_and_gaps,
+        optional_fields
+            .into_iter()
+            .filter(|field| field.starts_with("cg:") || field.starts_with("cs:"))
+            .collect::<Vec<_>>()
+            .join(" "),
+    )
+}
+
+fn example_line(strand: char, difference_string: &str) -> String {
+    format!("q\t100\t10\t40\t{strand}\tt\t200\t50\t78\t25\t33\t60\tNM:i:8\tcg:Z:10M5I10M3D5M\tcs:Z:{difference_string}")
+}
+
+#[test]
+fn split_forward_strand() {
+    let paf_line = parse_paf_line(&example_line('+', ":10+aaaaa:10-ccc:5"));
+    let segments: Vec<_> = split_on_gaps(paf_line, 4).iter().map(summary).collect();
+    assert_eq!(
+        segments,
+        [
+            (
+                10,
+                20,
+                50,
+                60,
+                10,
+                10,
+                Some(0),
+                "cg:Z:10M cs:Z::10".to_string()
+            ),
+            (
+                25,
+                40,
+                60,
+                78,
+                15,
+                18,
+                Some(3),
+                "cg:Z:10M3D5M cs:Z::10-ccc:5".to_string()
+            ),
+        ]
+    );
+}
+
+#[test]
+fn split_reverse_strand() {
+    let paf_line = parse_paf_line(&example_line('-', ":10+aaaaa:10-ccc:5"));
+    let segments: Vec<_> = split_on_gaps(paf_line, 4).iter().map(summary).collect();
+    // On the reverse strand, the first segment covers the end of the query range.
+    assert_eq!(
+        segments,
+        [
+            (
+                30,
+                40,
+                50,
+                60,
+                10,
+                10,
+                Some(0),
+                "cg:Z:10M cs:Z::10".to_string()
+            ),
+            (
+                10,
+                25,
+                60,
+                78,
+                15,
+                18,
+                Some(3),
+                "cg:Z:10M3D5M cs:Z::10-ccc:5".to_string()
+            ),
+        ]
+    );
+}
+
+#[test]
+fn no_split_at_short_gaps() {
+    let line = example_line('+', ":10+aaaaa:10-ccc:5");
+    let segments = split_on_gaps(parse_paf_line(&line), 5);
+    assert_eq!(segments, [parse_paf_line(&line)]);
+}
+
+#[test]
+fn no_split_if_cigar_and_difference_string_disagree() {
+    // The difference string has a short insertion where the CIGAR string has a long one.
+    let line = example_line('+', ":10+aa:10-ccc:5");
+    let segments = split_on_gaps(parse_paf_line(&line), 4);
+    assert_eq!(segments, [parse_paf_line(&line)]);
+}
+
+#[test]
+fn split_counts_like_decompression() {
+    // The mismatch expands into three copies and the insertion into six characters.
+    let query_maps = HashMap::from([("q".to_string(), map_from_runs(&[1, 1, 3, 1, 6, 1, 1, 1]))]);
+    let target_maps = HashMap::from([("t".to_string(), map_from_runs(&[1, 1, 3, 1, 1, 1, 1]))]);
+    let paf_line = parse_paf_line("q\t8\t0\t8\t+\tt\t7\t0\t7\t6\t8\t60\tcs:Z::2*ag:1+c:3");
+    let hodeco_paf = hodeco_paf_line(paf_line, &query_maps, &target_maps).unwrap();
+    assert_eq!(hodeco_paf.total_number_of_mismatches_and_gaps, Some(8));
+
+    let segments: Vec<_> = split_on_gaps(hodeco_paf, 4).iter().map(summary).collect();
+    // Without CIGAR string, the base counts of each segment are recomputed from its difference string.
+    assert_eq!(
+        segments,
+        [
+            (0, 6, 0, 6, 3, 6, Some(2), "cs:Z::2*ag*ag*ag:1".to_string()),
+            (12, 15, 6, 9, 3, 3, Some(0), "cs:Z::3".to_string()),
+        ]
+    );
+}